@@ -0,0 +1,59 @@
+#!/bin/bash
+set -ev
+
+#
+# Generate the test CA and the certificates it signs into telos/tests.
+# These are long lived, there should be no need to run this again.
+#
+
+DAYS=36500
+cd "$(dirname "$0")/../telos/tests"
+
+# Test CA
+openssl req -x509 -newkey rsa:2048 -nodes -days ${DAYS} \
+	-subj "/O=telos/CN=telos test CA" \
+	-keyout ca.key -out ca.crt
+
+# Server certificate for localhost
+openssl req -newkey rsa:2048 -nodes -subj "/O=telos/CN=localhost" \
+	-keyout server.key -out server.csr
+printf "subjectAltName=DNS:localhost,IP:127.0.0.1\nextendedKeyUsage=serverAuth\n" > server.ext
+openssl x509 -req -days ${DAYS} -in server.csr -CA ca.crt -CAkey ca.key \
+	-set_serial 1 -extfile server.ext -out server.crt
+
+# Second server certificate, selected via SNI
+openssl req -newkey rsa:2048 -nodes -subj "/O=telos/CN=example.org" \
+	-keyout sni.key -out sni.csr
+printf "subjectAltName=DNS:example.org\nextendedKeyUsage=serverAuth\n" > sni.ext
+openssl x509 -req -days ${DAYS} -in sni.csr -CA ca.crt -CAkey ca.key \
+	-set_serial 3 -extfile sni.ext -out sni.crt
+
+# Client certificate
+openssl req -newkey rsa:2048 -nodes -subj "/O=telos/CN=telos client" \
+	-keyout client.key -out client.csr
+printf "extendedKeyUsage=clientAuth\n" > client.ext
+openssl x509 -req -days ${DAYS} -in client.csr -CA ca.crt -CAkey ca.key \
+	-set_serial 2 -extfile client.ext -out client.crt
+
+# OCSP response for the server certificate, for stapling
+SERIAL=$(openssl x509 -in server.crt -noout -serial | cut -d= -f2)
+EXPIRY=$(date -u -d "+50 years" +%y%m%d%H%M%SZ)
+printf "V\t%s\t\t%s\tunknown\t/O=telos/CN=localhost\n" ${EXPIRY} ${SERIAL} > ocsp-index.txt
+openssl ocsp -issuer ca.crt -cert server.crt -no_nonce -reqout ocsp.req
+openssl ocsp -index ocsp-index.txt -rsigner ca.crt -rkey ca.key -CA ca.crt \
+	-reqin ocsp.req -respout server.ocsp -ndays ${DAYS}
+
+# Revoked certificate for localhost, listed in the CA's CRL
+openssl req -newkey rsa:2048 -nodes -subj "/O=telos/CN=localhost" \
+	-keyout revoked.key -out revoked.csr
+printf "subjectAltName=DNS:localhost,IP:127.0.0.1\nextendedKeyUsage=serverAuth,clientAuth\n" > revoked.ext
+openssl x509 -req -days ${DAYS} -in revoked.csr -CA ca.crt -CAkey ca.key \
+	-set_serial 4 -extfile revoked.ext -out revoked.crt
+
+REVOKED=$(date -u +%y%m%d%H%M%SZ)
+printf "R\t%s\t%s\t04\tunknown\t/O=telos/CN=localhost\n" ${EXPIRY} ${REVOKED} > crl-index.txt
+echo 01 > crl-number
+printf "[ca]\ndefault_ca = test_ca\n[test_ca]\ndatabase = crl-index.txt\ncrlnumber = crl-number\ndefault_md = sha256\ndefault_crl_days = ${DAYS}\n" > crl.cnf
+openssl ca -gencrl -config crl.cnf -keyfile ca.key -cert ca.crt -out ca.crl
+
+rm -f *.csr *.ext ocsp.req ocsp-index.txt* crl.cnf crl-index.txt* crl-number*
@@ -0,0 +1,47 @@
+//! Compare the cost of creating client connections from a new builder each
+//! time against reusing a `ClientConfig`
+//!
+//!     $ cargo run --release --example config_bench [iterations] [ca bundle]
+//!
+//! No network I/O takes place, each connection is set up over an in-memory
+//! stream and dropped before the handshake. Reusing the config saves reading
+//! the CA file and allocating the config, libtls still parses the CA bundle
+//! for every connection.
+
+extern crate telos;
+
+use std::env;
+use std::io;
+use std::time::{Duration, Instant};
+use telos::TlsBuilder;
+
+fn per_connection(elapsed: Duration, n: u32) -> f64 {
+    let nanos = elapsed.as_secs() as f64 * 1e9 + elapsed.subsec_nanos() as f64;
+    nanos / n as f64 / 1000.0
+}
+
+fn main() {
+    let n: u32 = env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(1000);
+    let ca = env::args().nth(2).unwrap_or("tests/cert.pem".to_owned());
+
+    let start = Instant::now();
+    for _ in 0..n {
+        telos::new_client()
+            .ca_file(&ca)
+            .connect_stream(io::Cursor::new(Vec::new()), "localhost")
+            .unwrap();
+    }
+    let builder = start.elapsed();
+
+    let start = Instant::now();
+    let config = telos::new_client().ca_file(&ca).build().unwrap();
+    for _ in 0..n {
+        config.connect_stream(io::Cursor::new(Vec::new()), "localhost").unwrap();
+    }
+    let shared = start.elapsed();
+
+    println!("{} connections, CA bundle {}", n, ca);
+    println!("new builder per connection: {:.1}us/connection", per_connection(builder, n));
+    println!("shared ClientConfig:        {:.1}us/connection", per_connection(shared, n));
+    println!("(the shared config skips the CA file read, not the CA parsing)");
+}
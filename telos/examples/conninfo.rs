@@ -1,9 +1,8 @@
 extern crate telos;
-extern crate rustc_serialize;
 extern crate docopt;
 
 use docopt::Docopt;
-use std::net::TcpStream;
+use telos::TlsBuilder;
 
 const USAGE: &'static str = "
 conninfo
@@ -21,45 +20,49 @@ Options:
                             noverifycert, noverifyname.
 ";
 
-#[derive(Debug,RustcDecodable)]
-struct Args {
-    arg_address: String,
-    arg_port: u16,
-    flag_protocols: String,
-    flag_ciphers: String,
-    flag_noverifycert: bool,
-    flag_noverifyname: bool,
-    flag_accept_all: bool,
-}
-
 fn main() {
-    let args: Args = Docopt::new(USAGE)
-                            .and_then(|d| d.decode())
-                            .unwrap_or_else(|e| e.exit());
+    let args = Docopt::new(USAGE)
+                      .and_then(|d| d.parse())
+                      .unwrap_or_else(|e| e.exit());
+    let protocols = args.get_str("--protocols");
+    let ciphers = args.get_str("--ciphers");
+    let accept_all = args.get_bool("--accept-all");
+    let port: u16 = match args.get_str("<port>").parse() {
+        Ok(port) => port,
+        Err(err) => {
+            println!("Invalid port: {}", err);
+            return;
+        }
+    };
 
     let mut c = telos::new_client()
         .ca(include_str!("../tests/cert.pem"));
 
-    if !args.flag_protocols.is_empty() {
-        c = c.protocols(&args.flag_protocols);
+    if !protocols.is_empty() {
+        match protocols.parse() {
+            Ok(protocols) => c = c.protocols(protocols),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        }
     }
-    if !args.flag_ciphers.is_empty() {
-        c = c.ciphers(&args.flag_ciphers);
+    if !ciphers.is_empty() {
+        c = c.ciphers(ciphers);
     }
-    if args.flag_noverifycert || args.flag_accept_all {
+    if args.get_bool("--noverifycert") || accept_all {
         c = c.insecure_noverifycert();
     }
-    if args.flag_noverifyname || args.flag_accept_all {
+    if args.get_bool("--noverifyname") || accept_all {
         c = c.insecure_noverifyname();
     }
 
-    if args.flag_accept_all {
-        c = c.protocols("all");
+    if accept_all {
+        c = c.protocols(telos::Protocols::ALL);
         c = c.ciphers("legacy");
     }
 
-    let tcp_stream = TcpStream::connect((&*args.arg_address, args.arg_port)).unwrap();
-    let mut stream = c.connect(tcp_stream, &args.arg_address).unwrap();
+    let mut stream = c.connect_to(args.get_str("<address>"), port).unwrap();
     stream.handshake().unwrap();
 
     println!("Certificate Issuer: {}", stream.certificate_issuer());
@@ -0,0 +1,118 @@
+extern crate telos;
+extern crate mio;
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use mio::{Events, Poll, PollOpt, Ready, Token};
+use mio::net::{TcpListener, TcpStream};
+use telos::{Status, TlsBuilder, TlsStream};
+
+/// TLS echo server on top of mio, try it with
+///
+///     $ cargo run --features mio --example mio_echo 127.0.0.1:8443
+///     $ openssl s_client -connect 127.0.0.1:8443
+
+const SERVER: Token = Token(0);
+
+struct Conn {
+    stream: TlsStream<TcpStream>,
+    pending: Vec<u8>,
+}
+
+impl Conn {
+    /// Echo back whatever can be read, returns false once the connection is closed
+    fn ready(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        loop {
+            while !self.pending.is_empty() {
+                match try!(self.stream.try_write(&self.pending)) {
+                    Status::Ready(len) => {
+                        self.pending.drain(..len);
+                    }
+                    Status::Blocked(_) => return Ok(true),
+                }
+            }
+            match try!(self.stream.try_read(&mut buf)) {
+                Status::Ready(0) => return Ok(false),
+                Status::Ready(len) => self.pending.extend_from_slice(&buf[..len]),
+                Status::Blocked(_) => return Ok(true),
+            }
+        }
+    }
+}
+
+fn main() {
+    let addr = env::args().nth(1).unwrap_or("127.0.0.1:8443".to_owned());
+    let addr = addr.parse().unwrap();
+
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind()
+        .unwrap();
+
+    let poll = Poll::new().unwrap();
+    let listener = TcpListener::bind(&addr).unwrap();
+    poll.register(&listener, SERVER, Ready::readable(), PollOpt::level()).unwrap();
+
+    let mut conns = HashMap::new();
+    let mut next_token = 1;
+    let mut events = Events::with_capacity(128);
+    loop {
+        poll.poll(&mut events, None).unwrap();
+        for event in events.iter() {
+            match event.token() {
+                SERVER => {
+                    let (tcp, peer) = match listener.accept() {
+                        Ok(conn) => conn,
+                        Err(_) => continue,
+                    };
+                    let stream = match tls_srv.accept(tcp) {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            println!("{}: {}", peer, err);
+                            continue;
+                        }
+                    };
+                    let token = Token(next_token);
+                    next_token += 1;
+                    poll.register(&stream, token, stream.interest(), PollOpt::level()).unwrap();
+                    conns.insert(token,
+                                 Conn {
+                                     stream: stream,
+                                     pending: Vec::new(),
+                                 });
+                }
+                token => {
+                    let open = match conns.get_mut(&token) {
+                        Some(conn) => {
+                            match conn.ready() {
+                                Ok(true) => {
+                                    // libtls tells us which way it is blocked
+                                    poll.reregister(&conn.stream,
+                                                    token,
+                                                    conn.stream.interest(),
+                                                    PollOpt::level())
+                                        .unwrap();
+                                    true
+                                }
+                                Ok(false) => false,
+                                Err(err) => {
+                                    println!("{:?}: {}", token, err);
+                                    false
+                                }
+                            }
+                        }
+                        None => continue,
+                    };
+                    if !open {
+                        if let Some(conn) = conns.remove(&token) {
+                            let _ = poll.deregister(&conn.stream);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
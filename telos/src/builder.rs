@@ -0,0 +1,118 @@
+//! Settings shared by `ClientBuilder` and `ServerBuilder`.
+
+use raw::{TlsConfig, TlsResult};
+use protocols::Protocols;
+use kex::{self, Curve, DheParams};
+
+/// Settings common to clients and servers. Import this trait to configure
+/// either builder:
+///
+/// ```no_run
+/// use telos::TlsBuilder;
+///
+/// let server = telos::new_server()
+///     .key_file("tests/server.key")
+///     .cert_file("tests/server.crt")
+///     .protocols(telos::Protocols::TLSV1_3)
+///     .prefer_ciphers_server()
+///     .bind();
+/// ```
+///
+/// Errors are kept until the client connects or the server is created, and
+/// settings after a failed setting are ignored.
+pub trait TlsBuilder: Sized {
+    /// Apply a setting to the underlying config, unless an earlier setting failed.
+    /// Servers keep the setting to rebuild their config, see
+    /// `TlsServer::add_ticket_key()`.
+    fn with_config<F>(self, f: F) -> Self
+        where F: Fn(&mut TlsConfig) -> TlsResult<()> + Send + Sync + 'static;
+
+    /// Load CA certificates, used to verify the peer certificate, from PEM file
+    fn ca_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_ca_file(&path))
+    }
+    /// Load CA certificates from folder
+    fn ca_path(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_ca_path(&path))
+    }
+    /// Use CA certificates from PEM string
+    fn ca(self, ca: &str) -> Self {
+        let ca = ca.to_string();
+        self.with_config(move |cfg| cfg.set_ca_mem(&ca))
+    }
+    /// Load certificate revocation lists (CRLs) from PEM file. Revoked peer
+    /// certificates fail the handshake with `TlsErrorKind::Revoked`.
+    fn crl_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_crl_file(&path))
+    }
+    /// Use certificate revocation lists from PEM in memory, see `crl_file()`
+    fn crl(self, crl: &[u8]) -> Self {
+        let crl = crl.to_vec();
+        self.with_config(move |cfg| cfg.set_crl_mem(&crl))
+    }
+    /// Maximum depth of the certificate chain when verifying the peer
+    fn verify_depth(self, depth: i32) -> Self {
+        self.with_config(move |cfg| {
+            cfg.set_verify_depth(depth);
+            Ok(())
+        })
+    }
+    /// Load the certificate from PEM file. Clients present it to servers that
+    /// request client certificates.
+    fn cert_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_cert_file(&path))
+    }
+    /// Load the private key for the certificate from PEM file
+    fn key_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_key_file(&path))
+    }
+    /// Set the protocol versions to enable, the default is `Protocols::DEFAULT`.
+    /// Use `str::parse()` for the libtls string form, e.g. `"tlsv1.2,tlsv1.3"`.
+    fn protocols(self, protocols: Protocols) -> Self {
+        self.with_config(move |cfg| {
+            cfg.set_protocols(protocols);
+            Ok(())
+        })
+    }
+    /// Set the allowed ciphers, e.g. `"secure"`, `"compat"` or an OpenSSL cipher list
+    fn ciphers(self, ciphers: &str) -> Self {
+        let ciphers = ciphers.to_string();
+        self.with_config(move |cfg| cfg.set_ciphers(&ciphers))
+    }
+    /// Pick the cipher from the server's list of preferences, the default
+    fn prefer_ciphers_server(self) -> Self {
+        self.with_config(|cfg| {
+            cfg.prefer_ciphers_server();
+            Ok(())
+        })
+    }
+    /// Pick the cipher from the client's list of preferences
+    fn prefer_ciphers_client(self) -> Self {
+        self.with_config(|cfg| {
+            cfg.prefer_ciphers_client();
+            Ok(())
+        })
+    }
+    /// Curves allowed for ECDHE key exchange, in order of preference
+    fn ecdhe_curves(self, curves: &[Curve]) -> Self {
+        let curves = kex::curves_to_string(curves);
+        self.with_config(move |cfg| cfg.set_ecdhecurves(&curves))
+    }
+    /// Parameters for DHE key exchange (server-side), DHE is disabled by default
+    fn dhe_params(self, params: DheParams) -> Self {
+        self.with_config(move |cfg| cfg.set_dheparams(params.name()))
+    }
+    /// Set the ALPN protocols, in order of preference
+    fn alpn(self, protocols: &[&str]) -> Self {
+        let protocols: Vec<String> = protocols.iter().map(|p| p.to_string()).collect();
+        self.with_config(move |cfg| {
+            let protocols: Vec<&str> = protocols.iter().map(|p| &p[..]).collect();
+            cfg.set_alpn(&protocols)
+        })
+    }
+}
@@ -0,0 +1,47 @@
+//! mio integration, enabled with the `mio` feature.
+//!
+//! `TlsStream<T>` is `Evented` when `T` is, e.g. `TlsStream<mio::net::TcpStream>` from
+//! `ClientBuilder::connect()` or `TlsServer::accept()`. libtls may need to read while
+//! writing (and vice versa), register the stream with `TlsStream::interest()` after
+//! each operation that returned `WouldBlock`/`Status::Blocked`.
+
+use std::io;
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+use super::{Interest, TlsStream};
+
+impl From<Interest> for Ready {
+    fn from(interest: Interest) -> Ready {
+        match interest {
+            Interest::Read => Ready::readable(),
+            Interest::Write => Ready::writable(),
+        }
+    }
+}
+
+impl<T> TlsStream<T> {
+    /// The readiness to register for. This is the readiness the last operation
+    /// blocked on, or readable if it did not block.
+    pub fn interest(&self) -> Ready {
+        match self.blocked_on() {
+            Some(interest) => Ready::from(interest),
+            None => Ready::readable(),
+        }
+    }
+}
+
+impl<T: Evented> Evented for TlsStream<T> {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        self.inner().register(poll, token, interest, opts)
+    }
+    fn reregister(&self,
+                  poll: &Poll,
+                  token: Token,
+                  interest: Ready,
+                  opts: PollOpt)
+                  -> io::Result<()> {
+        self.inner().reregister(poll, token, interest, opts)
+    }
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        self.inner().deregister(poll)
+    }
+}
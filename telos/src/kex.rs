@@ -0,0 +1,122 @@
+//! Key exchange settings, see `TlsBuilder::ecdhe_curves()` and
+//! `TlsBuilder::dhe_params()`.
+
+use std::fmt;
+
+/// A named curve for ECDHE key exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    X25519,
+    /// NIST P-256 (secp256r1)
+    P256,
+    /// NIST P-384 (secp384r1)
+    P384,
+    /// NIST P-521 (secp521r1)
+    P521,
+}
+
+impl Curve {
+    /// The curve name as understood by libtls
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Curve::X25519 => "X25519",
+            Curve::P256 => "P-256",
+            Curve::P384 => "P-384",
+            Curve::P521 => "P-521",
+        }
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Comma separated list of curve names, the libtls string form
+pub fn curves_to_string(curves: &[Curve]) -> String {
+    let names: Vec<&str> = curves.iter().map(|c| c.name()).collect();
+    names.join(",")
+}
+
+/// Diffie-Hellman parameters for DHE key exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DheParams {
+    /// Disable DHE key exchange, the libtls default
+    None,
+    /// Parameters sized to match the strength of the server key
+    Auto,
+    /// 1024 bit parameters, for legacy clients only
+    Legacy,
+}
+
+impl DheParams {
+    /// The libtls name for these parameters
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DheParams::None => "none",
+            DheParams::Auto => "auto",
+            DheParams::Legacy => "legacy",
+        }
+    }
+}
+
+/// The key exchange of a connection, see `TlsStream::key_exchange()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyExchange {
+    /// Elliptic curve Diffie-Hellman, always the case for TLSv1.3
+    Ecdhe,
+    /// Finite field Diffie-Hellman
+    Dhe,
+    /// Static elliptic curve Diffie-Hellman, without forward secrecy
+    Ecdh,
+    /// RSA key transport, without forward secrecy
+    Rsa,
+}
+
+impl KeyExchange {
+    /// The key exchange for a TLSv1.2 or earlier cipher suite name (as reported
+    /// by libtls), TLSv1.3 suites only use ECDHE in libressl. Anonymous suites
+    /// report their (unauthenticated) key exchange. None for suites that are not
+    /// recognised, e.g. PSK or GOST suites.
+    pub fn from_cipher(cipher: &str) -> Option<KeyExchange> {
+        let starts = |prefixes: &[&str]| prefixes.iter().any(|p| cipher.starts_with(p));
+        if starts(&["ECDHE-", "AECDH-", "AEAD-", "TLS_"]) {
+            Some(KeyExchange::Ecdhe)
+        } else if starts(&["DHE-", "EDH-", "ADH-"]) {
+            Some(KeyExchange::Dhe)
+        } else if starts(&["ECDH-"]) {
+            Some(KeyExchange::Ecdh)
+        } else if starts(&["AES", "CAMELLIA", "DES-", "RC4-", "IDEA-", "SEED-", "NULL-"]) {
+            // RSA suites are named after their cipher only
+            Some(KeyExchange::Rsa)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn key_exchange_from_cipher() {
+    assert_eq!(KeyExchange::from_cipher("ECDHE-RSA-AES256-GCM-SHA384"),
+               Some(KeyExchange::Ecdhe));
+    assert_eq!(KeyExchange::from_cipher("AEAD-AES256-GCM-SHA384"),
+               Some(KeyExchange::Ecdhe));
+    assert_eq!(KeyExchange::from_cipher("DHE-RSA-AES256-GCM-SHA384"),
+               Some(KeyExchange::Dhe));
+    assert_eq!(KeyExchange::from_cipher("AES256-GCM-SHA384"), Some(KeyExchange::Rsa));
+    assert_eq!(KeyExchange::from_cipher("DES-CBC3-SHA"), Some(KeyExchange::Rsa));
+    assert_eq!(KeyExchange::from_cipher("ECDH-RSA-AES256-GCM-SHA384"),
+               Some(KeyExchange::Ecdh));
+    assert_eq!(KeyExchange::from_cipher("ADH-AES256-GCM-SHA384"), Some(KeyExchange::Dhe));
+    assert_eq!(KeyExchange::from_cipher("AECDH-AES256-SHA"), Some(KeyExchange::Ecdhe));
+    assert_eq!(KeyExchange::from_cipher("PSK-AES256-CBC-SHA"), None);
+    assert_eq!(KeyExchange::from_cipher("GOST2001-GOST89-GOST89"), None);
+    assert_eq!(KeyExchange::from_cipher(""), None);
+}
+
+#[test]
+fn curve_names() {
+    assert_eq!(curves_to_string(&[Curve::X25519, Curve::P384]), "X25519,P-384");
+    assert_eq!(curves_to_string(&[]), "");
+}
@@ -16,16 +16,40 @@
 //! client.write("GET / HTTP/1.1\n\n".as_bytes()).unwrap();
 //! ```
 //!
+//! `ClientBuilder::connect_to` opens the TCP connection as well
+//!
+//! ```no_run
+//! let client = telos::new_client()
+//!     .connect_to("google.com", 443)
+//!     .unwrap();
+//! ```
+//!
 //! ## Server
 //!
-//! The library does not handle TCP listening and binding, you need to handle the
-//! TCP server accept() and then call `TlsServer::accept`
+//! `TlsListener` listens on a TCP socket and yields TLS streams once their handshake
+//! is complete
+//!
+//! ```no_run
+//! use telos::{TlsBuilder, TlsListener};
+//! let server = telos::new_server()
+//!     .key_file("tests/server.key")
+//!     .cert_file("tests/server.crt");
+//! let mut listener = TlsListener::bind(server, "127.0.0.1:0").unwrap();
+//! let tls_conn = listener.incoming().next().unwrap().unwrap();
+//! ```
+//!
+//! `PoolServer` goes further and runs a handler for each connection on a pool of
+//! worker threads, with connection limits, handshake timeouts and graceful shutdown.
+//!
+//! To handle TCP listening yourself, accept the TCP connection and then call
+//! `TlsServer::accept`
 //!
 //! ```no_run
 //! use std::net::TcpListener;
+//! use telos::TlsBuilder;
 //! let srv = TcpListener::bind("127.0.0.1:0").unwrap();
 //! let addr = srv.local_addr().unwrap();
-//! let mut tls_srv = telos::new_server()
+//! let tls_srv = telos::new_server()
 //!     .key_file("tests/private_key.key")
 //!     .cert_file("tests/certificate.crt")
 //!     .bind().unwrap();
@@ -34,12 +58,91 @@
 //! let mut tls_conn = tls_srv.accept(tcp_conn).unwrap();
 //! ```
 //!
+//! ## Other Transports
+//!
+//! TLS can also run over any `Read + Write` stream (in-memory pipes, tunnels, framed
+//! transports) using `ClientBuilder::connect_stream` and `TlsServer::accept_stream`.
+//! libtls then does all its I/O through callbacks into the stream.
+//!
+//! ```no_run
+//! use std::net::TcpStream;
+//! let tcp = TcpStream::connect("google.com:443").unwrap();
+//! let mut client = telos::new_client()
+//!     .connect_stream(tcp, "google.com")
+//!     .unwrap();
+//! client.handshake().unwrap();
+//! let addr = client.inner().get_ref().peer_addr().unwrap();
+//! ```
+//!
+//! ## Non-blocking I/O
+//!
+//! On non-blocking sockets `Read`/`Write` return `WouldBlock` errors. To know which
+//! readiness to wait for use the `try_*` methods of `TlsStream`, these return
+//! `Status::Blocked` with the `Interest` to register in your event loop.
+//!
+//! ```no_run
+//! use std::net::TcpStream;
+//! use telos::{Interest, Status};
+//! let tcp = TcpStream::connect("google.com:443").unwrap();
+//! tcp.set_nonblocking(true).unwrap();
+//! let mut client = telos::new_client()
+//!     .connect(tcp, "google.com")
+//!     .unwrap();
+//! loop {
+//!     match client.try_handshake().unwrap() {
+//!         Status::Ready(()) => break,
+//!         Status::Blocked(Interest::Read) => { /* wait until readable */ }
+//!         Status::Blocked(Interest::Write) => { /* wait until writable */ }
+//!     }
+//! }
+//! ```
+//!
+//! With the `mio` feature `TlsStream<T>` implements `mio::Evented` for any `Evented`
+//! `T`, and `TlsStream::interest()` gives the readiness to register for. See
+//! `examples/mio_echo.rs`.
+//!
+//! With the `tokio` feature the [tokio_support](tokio_support/index.html) module provides
+//! `AsyncRead`/`AsyncWrite` streams.
+//!
 //! ## Certificate Verification
 //!
 //! By default libtls will verify certificates using the system certificate store (usually defined
 //! as /etc/ssl/cert.pem). In some Linux flavours and in Windows this file does not exist and you
 //! will need to use one of the appropriate methods to load the correct certificates for your
-//! system - check the Builder classes for the ca methods.
+//! system - check the ca methods of `TlsBuilder`.
+//!
+//! Settings common to clients and servers (CA, protocols, ciphers, ...) come from
+//! the `TlsBuilder` trait, implemented by both `ClientBuilder` and `ServerBuilder`.
+//!
+//! ## Reusing Configuration
+//!
+//! Builders are consumed by each connection. To open many connections with the same
+//! settings, `build()` a `ClientConfig` or `ServerConfig` once and share it. Files
+//! are then read and the libtls config allocated only once, libtls still parses the
+//! CA bundle again for every connection. See `examples/config_bench.rs`.
+//!
+//! ## Client Certificates
+//!
+//! Servers can ask clients for a certificate with `ServerBuilder::verify_client` (or
+//! `verify_client_optional`), client certificates are verified against the CA set in
+//! the server builder. Once the handshake is complete the `certificate_*` and
+//! `peer_cert_*` methods of the accepted `TlsStream` describe the client certificate.
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//! use telos::TlsBuilder;
+//! let srv = TcpListener::bind("127.0.0.1:0").unwrap();
+//! let mut tls_srv = telos::new_server()
+//!     .key_file("tests/server.key")
+//!     .cert_file("tests/server.crt")
+//!     .ca_file("tests/ca.crt")
+//!     .verify_client()
+//!     .bind().unwrap();
+//! let tcp_conn = srv.incoming().next().unwrap().unwrap();
+//! let mut tls_conn = tls_srv.accept(tcp_conn).unwrap();
+//! tls_conn.handshake().unwrap();
+//! println!("Client: {}", tls_conn.certificate_subject());
+//! ```
 //!
 //! ## Connection Lifetime
 //!
@@ -75,14 +178,27 @@
 //! }
 //! ```
 
+#[macro_use]
+extern crate bitflags;
 extern crate chrono;
 extern crate libc;
+#[cfg(feature = "mio")]
+extern crate mio;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
-use std::error::Error;
+use std::cmp;
+#[cfg(unix)]
+use std::fs::OpenOptions;
 use std::io;
 use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 #[cfg(unix)]
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 #[cfg(windows)]
 use std::os::windows::io::AsRawSocket;
 use chrono::datetime::DateTime;
@@ -90,103 +206,175 @@ use chrono::offset::utc::UTC;
 
 mod util;
 pub mod raw;
-use raw::{TlsConfig, TlsContext};
+mod ocsp;
+mod pem;
+mod protocols;
+mod builder;
+mod kex;
+mod listener;
+mod pool;
+#[cfg(unix)]
+mod timeout;
+#[cfg(feature = "mio")]
+mod evented;
+#[cfg(feature = "tokio")]
+pub mod tokio_support;
+use raw::{ServerContext, TlsConfig, TlsContext};
+use util::Secret;
 
-pub use raw::{TlsResult, TlsError};
+pub use raw::{TlsResult, TlsError, TlsErrorKind, CallbackStream, StreamGuard, Interest, Status};
+pub use ocsp::{OcspStatus, OcspResponseStatus, OcspCertStatus, CrlReason};
+pub use pem::{pem_certificates, PemCertificates};
+pub use protocols::{Protocols, Protocol};
+pub use builder::TlsBuilder;
+pub use kex::{Curve, DheParams, KeyExchange};
+pub use listener::{TlsListener, Incoming};
+pub use pool::{PoolServer, PoolHandle};
 
 pub struct ClientBuilder {
     cfg: Option<TlsConfig>,
     error: Option<TlsError>,
+    pins: Vec<String>,
+    connect_timeout: Option<Duration>,
+    servername: Option<String>,
 }
 
-impl ClientBuilder {
-    /// Load CA certificates from PEM file
-    pub fn ca_file(mut self, path: &str) -> Self {
+impl TlsBuilder for ClientBuilder {
+    fn with_config<F>(mut self, f: F) -> Self
+        where F: Fn(&mut TlsConfig) -> TlsResult<()> + Send + Sync + 'static
+    {
         if self.error.is_some() {
             return self;
         }
         if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_ca_file(path).err();
+            self.error = f(cfg).err();
         }
         self
     }
-    /// Load CA certificates from folder
-    pub fn ca_path(mut self, path: &str) -> Self {
+}
+
+impl ClientBuilder {
+    /// Only accept servers whose certificate hash is one of `hashes`, in the
+    /// format returned by `TlsStream::certificate_hash()` (`SHA256:<hex>`).
+    /// The hash is checked as part of the handshake, reads and writes fail on
+    /// a server that is not pinned.
+    ///
+    /// Pinning applies on top of the usual certificate verification, combine it
+    /// with `insecure_noverifycert()` to accept pinned self-signed certificates.
+    /// libtls only exposes the hash of the whole certificate, pinning the public
+    /// key (SPKI) is not supported.
+    pub fn pin_hashes(mut self, hashes: &[&str]) -> Self {
         if self.error.is_some() {
             return self;
         }
-        if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_ca_path(path).err();
+        for hash in hashes {
+            if !hash.starts_with("SHA256:") {
+                self.error = Some(TlsError::with_kind(TlsErrorKind::Config,
+                                                      format!("invalid pinned hash: {}", hash)));
+                return self;
+            }
         }
+        self.pins.extend(hashes.iter().map(|h| h.to_string()));
         self
     }
-    /// Use CA certificates from PEM string
-    pub fn ca(mut self, ca: &str) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_ca_mem(ca).err();
-        }
-        self
+    /// Store the TLS session in the file at `path`, creating it if needed. Later
+    /// connections using the same file resume the session, skipping the full
+    /// handshake if the server allows it. See `TlsStream::session_resumed()`.
+    ///
+    /// The file is created readable only by its owner, libtls refuses files
+    /// accessible by group or others. A session file is for a single connection
+    /// at a time, `build()` refuses it.
+    #[cfg(unix)]
+    pub fn session_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o600)
+                .open(&path);
+            match file {
+                Ok(file) => cfg.set_session_file(file),
+                Err(err) => {
+                    Err(TlsError::with_kind(TlsErrorKind::Config,
+                                            format!("Unable to open session file {}: {}", path, err)))
+                }
+            }
+        })
     }
-    pub fn verify_depth(mut self, depth: i32) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
-            cfg.set_verify_depth(depth);
-        }
-        self
+    /// Fail the handshake unless the server staples a valid OCSP response for
+    /// its certificate. See `TlsStream::ocsp_status`.
+    pub fn ocsp_require_stapling(self) -> Self {
+        self.with_config(|cfg| {
+            cfg.ocsp_require_stapling();
+            Ok(())
+        })
     }
-    pub fn protocols(mut self, protocols: &str) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_protocols(protocols).err();
-        }
+    /// Complete the handshake as part of `connect()`, failing with
+    /// `TlsErrorKind::Timeout` if it takes longer than `timeout`. Otherwise the
+    /// handshake happens on the first read or write. Not available with
+    /// `connect_stream()`. In `connect_to()` this is also the TCP connect timeout.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
         self
     }
-    pub fn ciphers(mut self, ciphers: &str) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_ciphers(ciphers).err();
-        }
+    /// The name `connect_to()` and `connect_to_addr()` verify the server
+    /// certificate against and send to the server (SNI), instead of the host
+    /// they connect to
+    pub fn servername(mut self, name: &str) -> Self {
+        self.servername = Some(name.to_string());
         self
     }
     /// Disable certificate verification
-    pub fn insecure_noverifycert(mut self) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
+    pub fn insecure_noverifycert(self) -> Self {
+        self.with_config(|cfg| {
             cfg.insecure_noverifycert();
-        }
-        self
+            Ok(())
+        })
     }
     /// Disable hostname verification
-    pub fn insecure_noverifyname(mut self) -> Self {
-        if self.error.is_some() {
-            return self;
-        }
-        if let Some(cfg) = self.cfg.as_mut() {
+    pub fn insecure_noverifyname(self) -> Self {
+        self.with_config(|cfg| {
             cfg.insecure_noverifyname();
+            Ok(())
+        })
+    }
+
+    /// Build a reusable client configuration from these settings, see `ClientConfig`.
+    /// Fails if a session file is set, connections sharing the config would
+    /// race on it.
+    pub fn build(self) -> TlsResult<ClientConfig> {
+        if self.error.is_none() && self.has_session_file() {
+            return Err(TlsError::with_kind(TlsErrorKind::Config,
+                                           "a session file can't be shared between connections"));
         }
-        self
+        self.into_config()
     }
 
-    /// Create client context from settings
-    fn new_ctx(self) -> TlsResult<TlsContext> {
+    #[cfg(unix)]
+    fn has_session_file(&self) -> bool {
+        self.cfg.iter().any(|cfg| cfg.has_session_file())
+    }
+
+    #[cfg(windows)]
+    fn has_session_file(&self) -> bool {
+        false
+    }
+
+    /// The configuration for a single connection
+    fn into_config(self) -> TlsResult<ClientConfig> {
         if let Some(err) = self.error {
             Err(err)
         } else {
-            let mut cli = try!(TlsContext::new_client());
-            // This unwrap should be safe, we can't have a cfg without an error
-            try!(cli.configure(self.cfg.unwrap()));
-            Ok(cli)
+            Ok(ClientConfig {
+                // This unwrap should be safe, we can't have a cfg without an error
+                cfg: Arc::new(self.cfg.unwrap()),
+                pins: self.pins,
+                connect_timeout: self.connect_timeout,
+                servername: self.servername,
+            })
         }
     }
 
@@ -195,22 +383,42 @@ impl ClientBuilder {
     /// this does not take ownership, see the main crate docs [for an
     /// example](index.html#connection-lifetime).
     pub fn connect_socket<R: AsRawFd>(self, r: &R, servername: &str) -> TlsResult<TlsStream<()>> {
-        let mut ctx = try!(self.new_ctx());
-        try!(ctx.connect_socket(r.as_raw_fd(), servername));
-        Ok(TlsStream {
-            ctx: ctx,
-            inner_stream: (),
-        })
+        try!(self.into_config()).connect_socket(r, servername)
     }
     /// Connects over an existing stream. See `TlsStream::inner`.
     #[cfg(unix)]
     pub fn connect<F: AsRawFd>(self, inner_stream: F, servername: &str) -> TlsResult<TlsStream<F>> {
-        let mut ctx = try!(self.new_ctx());
-        try!(ctx.connect_socket(inner_stream.as_raw_fd(), servername));
-        Ok(TlsStream {
-            ctx: ctx,
-            inner_stream: inner_stream,
-        })
+        try!(self.into_config()).connect(inner_stream, servername)
+    }
+
+    /// Open a TCP connection to `host` and start TLS over it, the returned stream
+    /// owns the socket. Every address `host` resolves to is tried in turn, until
+    /// one accepts the connection.
+    ///
+    /// ```no_run
+    /// let client = telos::new_client().connect_to("example.com", 443).unwrap();
+    /// ```
+    pub fn connect_to(self, host: &str, port: u16) -> TlsResult<TlsStream<TcpStream>> {
+        try!(self.into_config()).connect_to(host, port)
+    }
+
+    /// Like `connect_to()`, with the host and port in a single `host:port` string
+    /// (`[addr]:port` for IPv6)
+    ///
+    /// ```no_run
+    /// let client = telos::new_client().connect_to_addr("example.com:443").unwrap();
+    /// ```
+    pub fn connect_to_addr(self, addr: &str) -> TlsResult<TlsStream<TcpStream>> {
+        try!(self.into_config()).connect_to_addr(addr)
+    }
+
+    /// Establish a TLS connection over any `Read + Write` stream. The stream is
+    /// owned by the returned `TlsStream`, see `CallbackStream` and `TlsStream::inner`.
+    pub fn connect_stream<S: Read + Write + 'static>(self,
+                                                      stream: S,
+                                                      servername: &str)
+                                                      -> TlsResult<TlsStream<CallbackStream<S>>> {
+        try!(self.into_config()).connect_stream(stream, servername)
     }
 
     #[cfg(windows)]
@@ -219,12 +427,7 @@ impl ClientBuilder {
                                           r: &R,
                                           servername: &str)
                                           -> TlsResult<TlsStream<()>> {
-        let mut ctx = try!(self.new_ctx());
-        try!(ctx.connect_socket(r.as_raw_socket(), servername));
-        Ok(TlsStream {
-            ctx: ctx,
-            inner_stream: (),
-        })
+        try!(self.into_config()).connect_socket(r, servername)
     }
 
     /// Consumes the socket holder, and keeps it
@@ -234,13 +437,157 @@ impl ClientBuilder {
                                    inner_stream: F,
                                    servername: &str)
                                    -> TlsResult<TlsStream<F>> {
+        try!(self.into_config()).connect(inner_stream, servername)
+    }
+}
+
+/// A client configuration that can be used for many connections, created with
+/// `ClientBuilder::build()`. CA and key files are read once, libtls still parses
+/// the CA certificates for every connection. The configuration can be shared
+/// between threads, e.g. in an `Arc`, it can't have a session file.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+/// use telos::TlsBuilder;
+///
+/// let config = telos::new_client().ca_file("tests/ca.crt").build().unwrap();
+/// for _ in 0..10 {
+///     let tcp = TcpStream::connect("localhost:8443").unwrap();
+///     let mut client = config.connect(tcp, "localhost").unwrap();
+///     client.handshake().unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ClientConfig {
+    cfg: Arc<TlsConfig>,
+    pins: Vec<String>,
+    connect_timeout: Option<Duration>,
+    servername: Option<String>,
+}
+
+impl ClientConfig {
+    /// Create client context from settings
+    fn new_ctx(&self) -> TlsResult<TlsContext> {
+        let mut cli = try!(TlsContext::new_client());
+        try!(cli.configure_shared(self.cfg.clone()));
+        if !self.pins.is_empty() {
+            let pins: Vec<&str> = self.pins.iter().map(|p| p.as_str()).collect();
+            cli.set_pins(&pins);
+        }
+        Ok(cli)
+    }
+
+    /// Complete the handshake if there is a connect timeout
+    #[cfg(unix)]
+    fn connected<T>(&self, mut stream: TlsStream<T>) -> TlsResult<TlsStream<T>> {
+        if let Some(timeout) = self.connect_timeout {
+            try!(stream.handshake_timeout(timeout));
+        }
+        Ok(stream)
+    }
+
+    /// See `ClientBuilder::connect_socket()`
+    #[cfg(unix)]
+    pub fn connect_socket<R: AsRawFd>(&self, r: &R, servername: &str) -> TlsResult<TlsStream<()>> {
+        let mut ctx = try!(self.new_ctx());
+        try!(ctx.connect_socket(r.as_raw_fd(), servername));
+        self.connected(TlsStream::with_socket(ctx, (), r.as_raw_fd()))
+    }
+    /// See `ClientBuilder::connect()`
+    #[cfg(unix)]
+    pub fn connect<F: AsRawFd>(&self, inner_stream: F, servername: &str) -> TlsResult<TlsStream<F>> {
+        let mut ctx = try!(self.new_ctx());
+        let fd = inner_stream.as_raw_fd();
+        try!(ctx.connect_socket(fd, servername));
+        self.connected(TlsStream::with_socket(ctx, inner_stream, fd))
+    }
+
+    /// See `ClientBuilder::connect_to()`
+    pub fn connect_to(&self, host: &str, port: u16) -> TlsResult<TlsStream<TcpStream>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut last_err = None;
+        for addr in try!((host, port).to_socket_addrs()) {
+            let tcp = match self.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match tcp {
+                Ok(tcp) => {
+                    let servername = match self.servername {
+                        Some(ref name) => name.as_str(),
+                        None => host,
+                    };
+                    return self.connect(tcp, servername);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(match last_err {
+            Some(err) => TlsError::from(err),
+            None => {
+                TlsError::with_kind(TlsErrorKind::Io(None),
+                                    format!("No addresses found for {}", host))
+            }
+        })
+    }
+
+    /// See `ClientBuilder::connect_to_addr()`
+    pub fn connect_to_addr(&self, addr: &str) -> TlsResult<TlsStream<TcpStream>> {
+        let (host, port) = try!(split_host_port(addr));
+        self.connect_to(host, port)
+    }
+
+    /// See `ClientBuilder::connect_stream()`
+    pub fn connect_stream<S: Read + Write + 'static>(&self,
+                                                      stream: S,
+                                                      servername: &str)
+                                                      -> TlsResult<TlsStream<CallbackStream<S>>> {
+        let mut ctx = try!(self.new_ctx());
+        let stream = CallbackStream::new(stream);
+        try!(ctx.connect_cbs(&stream, servername));
+        Ok(TlsStream::new(ctx, stream))
+    }
+
+    /// See `ClientBuilder::connect_socket()`
+    #[cfg(windows)]
+    pub fn connect_socket<R: AsRawSocket>(&self,
+                                          r: &R,
+                                          servername: &str)
+                                          -> TlsResult<TlsStream<()>> {
+        let mut ctx = try!(self.new_ctx());
+        try!(ctx.connect_socket(r.as_raw_socket(), servername));
+        Ok(TlsStream::new(ctx, ()))
+    }
+
+    /// See `ClientBuilder::connect()`
+    #[cfg(windows)]
+    pub fn connect<F: AsRawSocket>(&self,
+                                   inner_stream: F,
+                                   servername: &str)
+                                   -> TlsResult<TlsStream<F>> {
         let mut ctx = try!(self.new_ctx());
         let sock = inner_stream.as_raw_socket();
         try!(ctx.connect_socket(sock, servername));
-        Ok(TlsStream {
-            ctx: ctx,
-            inner_stream: inner_stream,
-        })
+        Ok(TlsStream::new(ctx, inner_stream))
+    }
+}
+
+/// Split the `host:port` given to `connect_to_addr()`, brackets around IPv6
+/// addresses are removed
+fn split_host_port(addr: &str) -> TlsResult<(&str, u16)> {
+    let (host, port) = match addr.rfind(':') {
+        Some(idx) if !addr[..idx].contains(':') || addr[..idx].ends_with(']') => {
+            (&addr[..idx], &addr[idx + 1..])
+        }
+        _ => {
+            return Err(TlsError::with_kind(TlsErrorKind::Config,
+                                           format!("Missing port in {}", addr)))
+        }
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match port.parse() {
+        Ok(port) => Ok((host, port)),
+        Err(_) => Err(TlsError::with_kind(TlsErrorKind::Config, format!("Invalid port: {}", port))),
     }
 }
 
@@ -250,6 +597,9 @@ pub fn new_client() -> ClientBuilder {
         return ClientBuilder {
             cfg: None,
             error: Some(TlsError::new("Failed to initialize libtls")),
+            pins: Vec::new(),
+            connect_timeout: None,
+            servername: None,
         };
     }
 
@@ -258,12 +608,18 @@ pub fn new_client() -> ClientBuilder {
             ClientBuilder {
                 cfg: Some(cfg),
                 error: None,
+                pins: Vec::new(),
+                connect_timeout: None,
+                servername: None,
             }
         }
         Err(err) => {
             ClientBuilder {
                 cfg: None,
                 error: Some(err),
+                pins: Vec::new(),
+                connect_timeout: None,
+                servername: None,
             }
         }
     }
@@ -272,28 +628,172 @@ pub fn new_client() -> ClientBuilder {
 pub struct TlsStream<T> {
     ctx: TlsContext,
     inner_stream: T,
+    blocked: Option<Interest>,
+    /// The socket libtls does I/O on, None for callback streams
+    #[cfg(unix)]
+    socket: Option<RawFd>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
 }
 
 impl<T> TlsStream<T> {
+    fn new(ctx: TlsContext, inner_stream: T) -> TlsStream<T> {
+        TlsStream {
+            ctx: ctx,
+            inner_stream: inner_stream,
+            blocked: None,
+            #[cfg(unix)]
+            socket: None,
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn with_socket(ctx: TlsContext, inner_stream: T, fd: RawFd) -> TlsStream<T> {
+        let mut stream = TlsStream::new(ctx, inner_stream);
+        stream.socket = Some(fd);
+        stream
+    }
+
+    /// Keep track of the readiness the last operation blocked on
+    fn track<R>(&mut self, res: TlsResult<R>) -> TlsResult<R> {
+        self.blocked = match res {
+            Err(ref err) => err.interest(),
+            Ok(_) => None,
+        };
+        res
+    }
+
+    /// Run a libtls operation, giving up after `timeout` if there is one
+    #[cfg(unix)]
+    fn timed<R, F>(&mut self, timeout: Option<Duration>, mut op: F) -> TlsResult<R>
+        where F: FnMut(&mut TlsContext) -> TlsResult<R>
+    {
+        let res = match (self.socket, timeout) {
+            (Some(fd), Some(timeout)) => {
+                let ctx = &mut self.ctx;
+                timeout::with_timeout(fd, timeout, || op(ctx))
+            }
+            _ => op(&mut self.ctx),
+        };
+        self.track(res)
+    }
+
+    #[cfg(windows)]
+    fn timed<R, F>(&mut self, _: Option<Duration>, mut op: F) -> TlsResult<R>
+        where F: FnMut(&mut TlsContext) -> TlsResult<R>
+    {
+        let res = op(&mut self.ctx);
+        self.track(res)
+    }
+
+    #[cfg(unix)]
+    fn check_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        if self.socket.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "timeouts are not supported over callback streams"));
+        }
+        if timeout == Some(Duration::new(0, 0)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "cannot set a 0 duration timeout"));
+        }
+        Ok(())
+    }
+
+    /// Fail reads that take longer than `timeout` with `TlsErrorKind::Timeout`
+    /// (`io::ErrorKind::TimedOut`), None blocks forever. This includes a pending
+    /// handshake. Timeouts are only available on sockets, not on callback streams.
+    ///
+    /// The socket is made non-blocking while an operation with a timeout runs.
+    /// O_NONBLOCK is shared by all copies of the socket (e.g. from `try_clone()`,
+    /// `PoolServer` keeps one), blocking I/O on them in the meantime may fail with
+    /// `WouldBlock`.
+    #[cfg(unix)]
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        try!(self.check_timeout(timeout));
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// Timeout for writes and `shutdown()`, see `set_read_timeout()`, including the
+    /// note on cloned sockets
+    #[cfg(unix)]
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        try!(self.check_timeout(timeout));
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    /// Like `handshake()`, but fails with `TlsErrorKind::Timeout` if the handshake
+    /// is not complete after `timeout`
+    #[cfg(unix)]
+    pub fn handshake_timeout(&mut self, timeout: Duration) -> TlsResult<()> {
+        if let Err(err) = self.check_timeout(Some(timeout)) {
+            return Err(TlsError::with_kind(TlsErrorKind::Config, err.to_string()));
+        }
+        self.timed(Some(timeout), |ctx| ctx.handshake())
+    }
+
     /// Executes the TLS handshake. This function is automatically called when reading or writing,
     /// you usually don't need to call it unless you want to force the handshake to finish sooner.
     ///
     /// Calling handshake multiple times, if the other end of the connection is not expecting it
     /// will usually result in an error.
     pub fn handshake(&mut self) -> TlsResult<()> {
-        self.ctx.handshake()
+        let res = self.ctx.handshake();
+        self.track(res)
+    }
+
+    /// Non-blocking variant of `handshake()`, returns `Status::Blocked` if the
+    /// handshake needs to wait for the socket to be readable or writable.
+    pub fn try_handshake(&mut self) -> TlsResult<Status<()>> {
+        let res = self.ctx.handshake();
+        Status::from_result(self.track(res))
+    }
+
+    /// Non-blocking read, see `try_handshake()`
+    pub fn try_read(&mut self, buf: &mut [u8]) -> TlsResult<Status<usize>> {
+        let res = self.ctx.read(buf);
+        Status::from_result(self.track(res))
+    }
+
+    /// Non-blocking write, see `try_handshake()`
+    pub fn try_write(&mut self, buf: &[u8]) -> TlsResult<Status<usize>> {
+        let res = self.ctx.write(buf);
+        Status::from_result(self.track(res))
+    }
+
+    /// Non-blocking variant of `shutdown()`, see `try_handshake()`
+    pub fn try_shutdown(&mut self) -> TlsResult<Status<()>> {
+        let res = self.ctx.close();
+        Status::from_result(self.track(res))
+    }
+
+    /// The readiness the last operation on this stream was blocked on, None if it
+    /// did not block. Useful when a `Read`/`Write` call returned `WouldBlock`.
+    pub fn blocked_on(&self) -> Option<Interest> {
+        self.blocked
     }
 
     /// Close TLS connection. This will not close the underlying transport.
     pub fn shutdown(&mut self) -> io::Result<()> {
-        if let Err(err) = self.ctx.close() {
-            if err.wants_more() {
-                try!(self.ctx.close());
-            } else {
-                return Err(io::Error::from(err));
+        let timeout = self.write_timeout;
+        // Retry once if libtls wants more, under the same deadline
+        self.timed(timeout, |ctx| {
+            match ctx.close() {
+                Err(ref err) if err.wants_more() => ctx.close(),
+                res => res,
             }
-        }
-        Ok(())
+        }).map_err(io::Error::from)
     }
 
     /// Calling this method before the handshake is complete causes this method
@@ -311,6 +811,8 @@ impl<T> TlsStream<T> {
     pub fn certificate_subject(&self) -> String {
         self.ctx.peer_cert_subject()
     }
+    /// True if the peer presented a certificate. On connections accepted by a
+    /// `TlsServer` this is the client certificate, see `ServerBuilder::verify_client`.
     pub fn peer_cert_provided(&self) -> bool {
         self.ctx.peer_cert_provided()
     }
@@ -323,16 +825,64 @@ impl<T> TlsStream<T> {
     pub fn peer_cert_contains_name(&self, name: &str) -> bool {
         self.ctx.peer_cert_contains_name(name)
     }
+    /// The full certificate chain presented by the peer in PEM format, leaf
+    /// certificate first. Use `pem_certificates()` to split it into individual
+    /// certificates. None before the handshake is complete or if the peer
+    /// did not present a certificate.
+    pub fn peer_cert_chain_pem(&self) -> Option<Vec<u8>> {
+        self.ctx.peer_cert_chain_pem()
+    }
     /// Calling this method before the handshake is complete causes this method
     /// to return an empty string. See [handshake()](#method.handshake).
     pub fn version(&self) -> String {
         self.ctx.conn_version()
     }
+    /// The protocol version of the connection, None before the handshake is
+    /// complete
+    pub fn protocol_version(&self) -> Option<Protocol> {
+        Protocol::from_conn_version(&self.ctx.conn_version())
+    }
     /// Calling this method before the handshake is complete causes this method
     /// to return an empty string. See [handshake()](#method.handshake).
     pub fn cipher(&self) -> String {
         self.ctx.conn_cipher()
     }
+    /// The key exchange used by the connection, derived from the cipher suite.
+    /// None before the handshake is complete. libtls does not report the
+    /// negotiated curve.
+    pub fn key_exchange(&self) -> Option<KeyExchange> {
+        KeyExchange::from_cipher(&self.ctx.conn_cipher())
+    }
+    /// The OCSP response stapled by the server, None before the handshake is complete
+    /// or if the server did not staple a response
+    pub fn ocsp_status(&self) -> Option<OcspStatus> {
+        OcspStatus::from_ctx(&self.ctx)
+    }
+    /// The OCSP responder URL in the peer certificate, if any
+    pub fn ocsp_url(&self) -> Option<String> {
+        let url = self.ctx.peer_ocsp_url();
+        if url.is_empty() {
+            None
+        } else {
+            Some(url)
+        }
+    }
+    /// On connections accepted by a `TlsServer`, the server name requested by the
+    /// client (SNI). None before the handshake is complete or if the client did not
+    /// request a name.
+    pub fn servername(&self) -> Option<String> {
+        self.ctx.conn_servername()
+    }
+    /// True if the session was resumed from a previous connection, see
+    /// `ClientBuilder::session_file()`
+    pub fn session_resumed(&self) -> bool {
+        self.ctx.conn_session_resumed()
+    }
+    /// The protocol selected by ALPN, None before the handshake is complete or if
+    /// the peers have no protocol in common. See `ClientBuilder::alpn`.
+    pub fn alpn_selected(&self) -> Option<String> {
+        self.ctx.conn_alpn_selected()
+    }
 
     /// Returns a reference to the inner object holding the
     /// socket.
@@ -348,17 +898,15 @@ impl<T> TlsStream<T> {
 
 impl<T> Read for TlsStream<T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.ctx
-            .read(buf)
-            .map_err(|err| io::Error::from(err))
+        let timeout = self.read_timeout;
+        self.timed(timeout, |ctx| ctx.read(buf)).map_err(io::Error::from)
     }
 }
 
 impl<T> Write for TlsStream<T> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.ctx
-            .write(buf)
-            .map_err(|err| io::Error::from(err))
+        let timeout = self.write_timeout;
+        self.timed(timeout, |ctx| ctx.write(buf)).map_err(io::Error::from)
     }
     fn flush(&mut self) -> io::Result<()> {
         Ok(())
@@ -371,45 +919,221 @@ impl<T> Drop for TlsStream<T> {
     }
 }
 
+// A libtls connection can move between threads, as long as it is only used
+// from one at a time. Callback streams share their state with the context, so
+// the stream is Send only if `T` (e.g. `CallbackStream<S>`) is.
+unsafe impl<T: Send> Send for TlsStream<T> {}
+
+/// libtls keeps the 4 newest ticket keys
+const TICKET_KEYS: usize = 4;
+
+/// A server setting, see `TlsBuilder::with_config()`
+type Setting = Arc<dyn Fn(&mut TlsConfig) -> TlsResult<()> + Send + Sync>;
+
+/// The settings a server config was built from. libtls configs can't be copied,
+/// servers replay the settings to build a config with new ticket keys.
+#[derive(Clone, Default)]
+struct ServerSettings {
+    settings: Vec<Setting>,
+    ticket_keys: Vec<(u32, Arc<Secret>)>,
+}
+
+impl ServerSettings {
+    /// Whether `(keyrev, key)` is the newest ticket key
+    fn is_current_ticket_key(&self, keyrev: u32, key: &[u8]) -> bool {
+        match self.ticket_keys.last() {
+            Some(&(rev, ref current)) => rev == keyrev && &current[..] == key,
+            None => false,
+        }
+    }
+
+    /// Add a ticket key, forgetting the keys libtls no longer keeps
+    fn add_ticket_key(&mut self, keyrev: u32, key: &[u8]) {
+        if self.is_current_ticket_key(keyrev, key) {
+            return;
+        }
+        self.ticket_keys.push((keyrev, Arc::new(Secret::new(key))));
+        if self.ticket_keys.len() > TICKET_KEYS {
+            let old = self.ticket_keys.len() - TICKET_KEYS;
+            self.ticket_keys.drain(..old);
+        }
+    }
+
+    /// Create a server context from a new config built from the settings
+    fn new_ctx(&self) -> TlsResult<TlsContext> {
+        let mut cfg = try!(TlsConfig::new());
+        for setting in &self.settings {
+            try!(setting(&mut cfg));
+        }
+        for &(keyrev, ref key) in &self.ticket_keys {
+            try!(cfg.add_ticket_key(keyrev, key));
+        }
+        new_server_ctx(cfg)
+    }
+}
+
+/// Create a server context that owns `cfg`
+fn new_server_ctx(cfg: TlsConfig) -> TlsResult<TlsContext> {
+    let mut ctx = try!(TlsContext::new_server());
+    try!(ctx.configure(cfg));
+    // The keys were loaded by configure(), don't keep them around
+    if let Some(cfg) = ctx.config_mut() {
+        cfg.clear_keys();
+    }
+    Ok(ctx)
+}
+
 pub struct ServerBuilder {
     cfg: Option<TlsConfig>,
     error: Option<TlsError>,
+    settings: ServerSettings,
 }
 
-impl ServerBuilder {
-    pub fn key_file(mut self, path: &str) -> Self {
+impl TlsBuilder for ServerBuilder {
+    fn with_config<F>(mut self, f: F) -> Self
+        where F: Fn(&mut TlsConfig) -> TlsResult<()> + Send + Sync + 'static
+    {
         if self.error.is_some() {
             return self;
         }
         if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_key_file(path).err();
+            self.error = f(cfg).err();
         }
+        self.settings.settings.push(Arc::new(f));
         self
     }
-    pub fn cert_file(mut self, path: &str) -> Self {
+}
+
+impl ServerBuilder {
+    /// Session ID used by the server session cache, servers that share
+    /// ticket keys must also share the session ID
+    pub fn session_id(self, id: &[u8]) -> Self {
+        let id = id.to_vec();
+        self.with_config(move |cfg| cfg.set_session_id(&id))
+    }
+    /// Enable session resumption, sessions and tickets expire after `lifetime`.
+    /// Without ticket keys (see `ticket_key()`) libtls generates and rotates
+    /// its own keys.
+    pub fn session_lifetime(self, lifetime: Duration) -> Self {
+        let secs = cmp::min(lifetime.as_secs(), i32::MAX as u64) as i32;
+        self.with_config(move |cfg| cfg.set_session_lifetime(secs))
+    }
+    /// Add a session ticket key, `key` must be 48 bytes. See
+    /// `TlsServer::add_ticket_key()` to rotate keys on a running server.
+    pub fn ticket_key(mut self, keyrev: u32, key: &[u8]) -> Self {
         if self.error.is_some() {
             return self;
         }
         if let Some(cfg) = self.cfg.as_mut() {
-            self.error = cfg.set_cert_file(path).err();
+            self.error = cfg.add_ticket_key(keyrev, key).err();
         }
+        self.settings.add_ticket_key(keyrev, key);
         self
     }
+    /// Use private key from PEM in memory. libtls keeps its own copy of the
+    /// key, which is zeroed once the server is created in `bind()`. The server
+    /// keeps another copy to rebuild its config (see `TlsServer::add_ticket_key()`),
+    /// zeroed when the server is dropped. Clearing `key` is up to the caller.
+    pub fn key(self, key: &[u8]) -> Self {
+        let key = Secret::new(key);
+        self.with_config(move |cfg| cfg.set_key_mem(&key))
+    }
+    /// Use certificate from PEM in memory
+    pub fn cert(self, cert: &[u8]) -> Self {
+        let cert = cert.to_vec();
+        self.with_config(move |cfg| cfg.set_cert_mem(&cert))
+    }
+    /// Use certificate and private key from PEM in memory, see `key()`
+    pub fn keypair(self, cert: &[u8], key: &[u8]) -> Self {
+        let (cert, key) = (cert.to_vec(), Secret::new(key));
+        self.with_config(move |cfg| cfg.set_keypair_mem(&cert, &key))
+    }
+    /// Add another certificate and key from PEM files. The first keypair (`cert_file`
+    /// and `key_file`) is the default, additional keypairs are used when the name
+    /// requested by the client (SNI) matches their certificate.
+    pub fn add_keypair_file(self, cert_file: &str, key_file: &str) -> Self {
+        let (cert_file, key_file) = (cert_file.to_string(), key_file.to_string());
+        self.with_config(move |cfg| cfg.add_keypair_file(&cert_file, &key_file))
+    }
+    /// Add another certificate and key from PEM in memory, see `add_keypair_file()`
+    /// and `key()`
+    pub fn add_keypair(self, cert: &[u8], key: &[u8]) -> Self {
+        let (cert, key) = (cert.to_vec(), Secret::new(key));
+        self.with_config(move |cfg| cfg.add_keypair_mem(&cert, &key))
+    }
+    /// Staple the OCSP response (DER) in `path` to the server certificate
+    pub fn ocsp_staple_file(self, path: &str) -> Self {
+        let path = path.to_string();
+        self.with_config(move |cfg| cfg.set_ocsp_staple_file(&path))
+    }
+    /// Staple an OCSP response (DER) to the server certificate
+    pub fn ocsp_staple(self, staple: &[u8]) -> Self {
+        let staple = staple.to_vec();
+        self.with_config(move |cfg| cfg.set_ocsp_staple_mem(&staple))
+    }
+    /// Require clients to present a certificate signed by one of the
+    /// configured CAs, connections without one fail the handshake
+    pub fn verify_client(self) -> Self {
+        self.with_config(|cfg| {
+            cfg.verify_client();
+            Ok(())
+        })
+    }
+    /// Verify client certificates when presented, but also accept clients
+    /// without a certificate. Check `TlsStream::peer_cert_provided`.
+    pub fn verify_client_optional(self) -> Self {
+        self.with_config(|cfg| {
+            cfg.verify_client_optional();
+            Ok(())
+        })
+    }
 
-    /// Create server context from settings
-    fn new_ctx(self) -> TlsResult<TlsContext> {
+    pub fn bind(self) -> TlsResult<TlsServer> {
         if let Some(err) = self.error {
             Err(err)
         } else {
-            let mut cli = try!(TlsContext::new_server());
             // This unwrap should be safe, we can't have a cfg without an error
-            try!(cli.configure(self.cfg.unwrap()));
-            Ok(cli)
+            let ctx = try!(new_server_ctx(self.cfg.unwrap()));
+            Ok(TlsServer::new(ctx, self.settings))
         }
     }
-    pub fn bind(self) -> TlsResult<TlsServer> {
+    /// Build a reusable server configuration, to create several `TlsServer`s
+    /// with the same settings. See `ServerConfig`.
+    pub fn build(self) -> TlsResult<ServerConfig> {
+        match self.error {
+            Some(err) => Err(err),
+            // This unwrap should be safe, we can't have a cfg without an error
+            None => {
+                Ok(ServerConfig {
+                    cfg: Arc::new(self.cfg.unwrap()),
+                    settings: self.settings,
+                })
+            }
+        }
+    }
+}
+
+/// A server configuration that can create many `TlsServer`s, created with
+/// `ServerBuilder::build()`. It can be shared between threads, e.g. in an `Arc`.
+///
+/// Unlike `ServerBuilder::bind()` the private keys stay in memory for as long
+/// as the configuration is alive, since each new server needs them.
+#[derive(Clone)]
+pub struct ServerConfig {
+    cfg: Arc<TlsConfig>,
+    settings: ServerSettings,
+}
+
+impl ServerConfig {
+    /// Create a server context from the configuration
+    fn new_ctx(&self) -> TlsResult<TlsContext> {
+        let mut ctx = try!(TlsContext::new_server());
+        try!(ctx.configure_shared(self.cfg.clone()));
+        Ok(ctx)
+    }
+    pub fn bind(&self) -> TlsResult<TlsServer> {
         let ctx = try!(self.new_ctx());
-        Ok(TlsServer { ctx: ctx })
+        Ok(TlsServer::new(ctx, self.settings.clone()))
     }
 }
 
@@ -419,6 +1143,7 @@ pub fn new_server() -> ServerBuilder {
         return ServerBuilder {
             cfg: None,
             error: Some(TlsError::new("Failed to initialize libtls")),
+            settings: ServerSettings::default(),
         };
     }
 
@@ -427,63 +1152,159 @@ pub fn new_server() -> ServerBuilder {
             ServerBuilder {
                 cfg: Some(cfg),
                 error: None,
+                settings: ServerSettings::default(),
             }
         }
         Err(err) => {
             ServerBuilder {
                 cfg: None,
                 error: Some(err),
+                settings: ServerSettings::default(),
             }
         }
     }
 }
 
 /// TLS Server, used to start TLS session over existing sockets.
+///
+/// A `TlsServer` can be shared between threads (e.g. in an `Arc`) and accept
+/// connections from all of them. `tls_accept_socket()` records failures in
+/// the server context, so the accept calls themselves take turns; this is
+/// cheap since the handshake runs later on the new connection.
 pub struct TlsServer {
-    ctx: TlsContext,
+    state: Mutex<ServerState>,
+}
+
+/// The context new connections are accepted from, and the settings it was built from
+struct ServerState {
+    ctx: Arc<ServerContext>,
+    settings: ServerSettings,
 }
 
 impl TlsServer {
+    fn new(ctx: TlsContext, settings: ServerSettings) -> TlsServer {
+        TlsServer {
+            state: Mutex::new(ServerState {
+                ctx: Arc::new(ServerContext::new(ctx)),
+                settings: settings,
+            }),
+        }
+    }
+
+    /// The server state, a panic while holding the lock leaves it usable
+    fn state(&self) -> MutexGuard<'_, ServerState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Accept a connection from the current server context with `accept`, the
+    /// connection keeps that context alive
+    fn accept_with<F>(&self, accept: F) -> TlsResult<TlsContext>
+        where F: FnOnce(&mut TlsContext) -> TlsResult<TlsContext>
+    {
+        let server = self.state().ctx.clone();
+        let mut conn = try!(accept(&mut server.lock()));
+        conn.set_server(server);
+        Ok(conn)
+    }
+
+    /// Add a new session ticket key, used to encrypt tickets for new
+    /// connections. Up to 3 previous keys are kept to decrypt tickets issued
+    /// before the rotation, until they expire (see `ServerBuilder::session_lifetime`).
+    ///
+    /// To share tickets across a fleet, every server adds the same `(keyrev, key)`
+    /// on the same schedule, e.g. using `ticket_key_rev()` to pick the revision
+    /// and the key for that revision from a shared secret store. Adding the
+    /// current key again is a no-op.
+    ///
+    /// The server builds a new config from its settings with the new key, key
+    /// and certificate files are read again. Connections already accepted keep
+    /// the previous config. Sessions in the server session cache are not
+    /// resumed after the rotation, tickets are.
+    pub fn add_ticket_key(&self, keyrev: u32, key: &[u8]) -> TlsResult<()> {
+        let mut state = self.state();
+        if state.settings.is_current_ticket_key(keyrev, key) {
+            return Ok(());
+        }
+        let mut settings = state.settings.clone();
+        settings.add_ticket_key(keyrev, key);
+        let ctx = try!(settings.new_ctx());
+        state.ctx = Arc::new(ServerContext::new(ctx));
+        state.settings = settings;
+        Ok(())
+    }
+
+    /// Use `config` for new connections, connections already accepted keep the
+    /// previous configuration, and the previous server context, until they are
+    /// dropped. Use `add_ticket_key()` to only rotate ticket keys.
+    ///
+    /// ```no_run
+    /// use telos::TlsBuilder;
+    ///
+    /// let config = |cert: &str, key: &str| {
+    ///     telos::new_server()
+    ///         .key_file(key)
+    ///         .cert_file(cert)
+    ///         .build()
+    ///         .unwrap()
+    /// };
+    /// let tls_srv = config("tests/server.crt", "tests/server.key").bind().unwrap();
+    /// // ...
+    /// tls_srv.reconfigure(&config("tests/sni.crt", "tests/sni.key")).unwrap();
+    /// ```
+    pub fn reconfigure(&self, config: &ServerConfig) -> TlsResult<()> {
+        let ctx = try!(config.new_ctx());
+        let mut state = self.state();
+        state.ctx = Arc::new(ServerContext::new(ctx));
+        state.settings = config.settings.clone();
+        Ok(())
+    }
+
+    /// The ticket key revision for the current time when keys are rotated
+    /// every `period`, so that servers rotating on the same schedule agree on it
+    pub fn ticket_key_rev(period: Duration) -> u32 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+        let period = cmp::max(period.as_secs(), 1);
+        (now.as_secs() / period) as u32
+    }
+
     #[cfg(unix)]
     /// Start a new TLS connection over an existing file descriptor (server-side)
     /// Unlike `connect()` this does not take ownership, see the main crate docs [for an
     /// example](index.html#connection-lifetime).
 
-    pub fn accept_socket<R: AsRawFd>(&mut self, r: &R) -> io::Result<TlsStream<()>> {
-        let c = try!(self.ctx.accept_socket(r.as_raw_fd()));
-        Ok(TlsStream {
-            ctx: c,
-            inner_stream: (),
-        })
+    pub fn accept_socket<R: AsRawFd>(&self, r: &R) -> io::Result<TlsStream<()>> {
+        let c = try!(self.accept_with(|ctx| ctx.accept_socket(r.as_raw_fd())));
+        Ok(TlsStream::with_socket(c, (), r.as_raw_fd()))
     }
     #[cfg(unix)]
-    pub fn accept<F: AsRawFd>(&mut self, inner_stream: F) -> io::Result<TlsStream<F>> {
+    pub fn accept<F: AsRawFd>(&self, inner_stream: F) -> io::Result<TlsStream<F>> {
         let fd = inner_stream.as_raw_fd();
-        let c = try!(self.ctx.accept_socket(fd));
-        Ok(TlsStream {
-            ctx: c,
-            inner_stream: inner_stream,
-        })
+        let c = try!(self.accept_with(|ctx| ctx.accept_socket(fd)));
+        Ok(TlsStream::with_socket(c, inner_stream, fd))
+    }
+
+    /// Start a new TLS connection over any `Read + Write` stream (server-side).
+    /// See `ClientBuilder::connect_stream`.
+    pub fn accept_stream<S: Read + Write + 'static>(&self,
+                                                 stream: S)
+                                                 -> io::Result<TlsStream<CallbackStream<S>>> {
+        let stream = CallbackStream::new(stream);
+        let c = try!(self.accept_with(|ctx| ctx.accept_cbs(&stream)));
+        Ok(TlsStream::new(c, stream))
     }
 
     #[cfg(windows)]
     /// Start a new TLS connection over an existing socket (server-side)
-    pub fn accept_socket<R: AsRawSocket>(&mut self, r: &R) -> TlsResult<TlsStream<()>> {
-        let c = try!(self.ctx.accept_socket(r.as_raw_socket()));
-        Ok(TlsStream {
-            ctx: c,
-            inner_stream: (),
-        })
+    pub fn accept_socket<R: AsRawSocket>(&self, r: &R) -> TlsResult<TlsStream<()>> {
+        let c = try!(self.accept_with(|ctx| ctx.accept_socket(r.as_raw_socket())));
+        Ok(TlsStream::new(c, ()))
     }
 
     #[cfg(windows)]
-    pub fn accept<F: AsRawSocket>(&mut self, inner_stream: F) -> TlsResult<TlsStream<F>> {
+    pub fn accept<F: AsRawSocket>(&self, inner_stream: F) -> TlsResult<TlsStream<F>> {
         let sock = inner_stream.as_raw_socket();
-        let c = try!(self.ctx.accept_socket(sock));
-        Ok(TlsStream {
-            ctx: c,
-            inner_stream: inner_stream,
-        })
+        let c = try!(self.accept_with(|ctx| ctx.accept_socket(sock)));
+        Ok(TlsStream::new(c, inner_stream))
     }
 }
 
@@ -492,17 +1313,30 @@ fn test_protocols() {
     let mut cfg = TlsConfig::new().unwrap();
 
     // The following are all supported
-    cfg.set_protocols("all").unwrap();
-    cfg.set_protocols("legacy").unwrap();
-    cfg.set_protocols("default").unwrap();
-    cfg.set_protocols("secure").unwrap();
-    cfg.set_protocols("tlsv1").unwrap();
-    cfg.set_protocols("tlsv1.0").unwrap();
-    cfg.set_protocols("tlsv1.1").unwrap();
-    cfg.set_protocols("tlsv1.2").unwrap();
+    cfg.set_protocols("all".parse().unwrap());
+    cfg.set_protocols("legacy".parse().unwrap());
+    cfg.set_protocols("default".parse().unwrap());
+    cfg.set_protocols("secure".parse().unwrap());
+    cfg.set_protocols("tlsv1".parse().unwrap());
+    cfg.set_protocols("tlsv1.0".parse().unwrap());
+    cfg.set_protocols("tlsv1.1".parse().unwrap());
+    cfg.set_protocols("tlsv1.2".parse().unwrap());
+    cfg.set_protocols("tlsv1.3".parse().unwrap());
+    cfg.set_protocols(Protocols::TLSV1_2 | Protocols::TLSV1_3);
 
     // This is not valid
-    assert!(cfg.set_protocols("unknown-proto").is_err());
+    assert!("unknown-proto".parse::<Protocols>().is_err());
+}
+
+#[test]
+fn host_port() {
+    assert_eq!(split_host_port("example.com:443").unwrap(), ("example.com", 443));
+    assert_eq!(split_host_port("[::1]:443").unwrap(), ("::1", 443));
+    assert!(split_host_port("example.com").is_err());
+    assert!(split_host_port("::1").is_err());
+    assert!(split_host_port("[::1]").is_err());
+    assert!(split_host_port("example.com:https").is_err());
+    assert!(split_host_port("example.com:70000").is_err());
 }
 
 #[test]
@@ -519,4 +1353,6 @@ fn client_ctx_defs() {
     assert_eq!(c.peer_cert_hash(), "");
     assert_eq!(c.peer_cert_contains_name("some.name"), false);
     assert_eq!(c.peer_cert_provided(), false);
+    assert_eq!(c.peer_cert_chain_pem(), None);
+    assert_eq!(c.conn_session_resumed(), false);
 }
@@ -0,0 +1,128 @@
+//! TLS server that owns its TCP listener.
+
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use super::{ServerBuilder, TlsServer, TlsStream};
+
+/// How long `TlsListener::accept()` waits for a handshake by default
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TLS server listening on a TCP socket
+///
+/// ```no_run
+/// use std::io::Write;
+/// use telos::{TlsBuilder, TlsListener};
+///
+/// let server = telos::new_server()
+///     .key_file("tests/server.key")
+///     .cert_file("tests/server.crt");
+/// let mut listener = TlsListener::bind(server, "127.0.0.1:8443").unwrap();
+/// for conn in listener.incoming() {
+///     match conn {
+///         Ok(mut stream) => { let _ = stream.write(b"hello"); }
+///         Err(err) => println!("{}", err),
+///     }
+/// }
+/// ```
+pub struct TlsListener {
+    server: TlsServer,
+    listener: TcpListener,
+    handshake_timeout: Duration,
+}
+
+impl TlsListener {
+    /// Create the TLS server from `builder` and listen on `addr`
+    pub fn bind<A: ToSocketAddrs>(builder: ServerBuilder, addr: A) -> io::Result<TlsListener> {
+        let server = try!(builder.bind());
+        let listener = try!(TcpListener::bind(addr));
+        Ok(TlsListener::new(server, listener))
+    }
+
+    /// Accept TLS connections from an existing TCP listener
+    pub fn new(server: TlsServer, listener: TcpListener) -> TlsListener {
+        TlsListener {
+            server: server,
+            listener: listener,
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+        }
+    }
+
+    /// Drop connections that do not complete the TLS handshake within `timeout`,
+    /// 10 seconds by default. See `TlsStream::handshake_timeout()`.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The underlying TCP listener
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    /// The TLS server, e.g. to rotate its ticket keys with `add_ticket_key()`
+    pub fn server(&self) -> &TlsServer {
+        &self.server
+    }
+
+    /// Accept a new connection and complete the TLS handshake. This blocks until
+    /// the handshake is done, a slow client holds up the following connections
+    /// for up to the handshake timeout, see `handshake_timeout()`.
+    pub fn accept(&mut self) -> io::Result<(TlsStream<TcpStream>, SocketAddr)> {
+        let (tcp, addr) = try!(self.listener.accept());
+        let stream = try!(accept_handshake(&self.server, tcp, Some(self.handshake_timeout)));
+        Ok((stream, addr))
+    }
+
+    /// Iterator over accepted connections, see `accept()`. Failed connections
+    /// are returned as errors, the iterator never ends.
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+}
+
+/// Iterator over the connections of a `TlsListener`, see `TlsListener::incoming()`
+pub struct Incoming<'a> {
+    listener: &'a mut TlsListener,
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = io::Result<TlsStream<TcpStream>>;
+
+    fn next(&mut self) -> Option<io::Result<TlsStream<TcpStream>>> {
+        Some(self.listener.accept().map(|(stream, _)| stream))
+    }
+}
+
+/// Accept `tcp` on `server` and complete the handshake, giving up after `timeout`
+#[cfg(unix)]
+pub fn accept_handshake(server: &TlsServer,
+                        tcp: TcpStream,
+                        timeout: Option<Duration>)
+                        -> io::Result<TlsStream<TcpStream>> {
+    let mut stream = try!(server.accept(tcp));
+    match timeout {
+        Some(timeout) => try!(stream.handshake_timeout(timeout)),
+        None => try!(stream.handshake()),
+    }
+    Ok(stream)
+}
+
+// Without TlsStream timeouts, fall back to socket timeouts
+#[cfg(not(unix))]
+pub fn accept_handshake(server: &TlsServer,
+                        tcp: TcpStream,
+                        timeout: Option<Duration>)
+                        -> io::Result<TlsStream<TcpStream>> {
+    try!(tcp.set_read_timeout(timeout));
+    try!(tcp.set_write_timeout(timeout));
+    let mut stream = try!(server.accept(tcp));
+    try!(stream.handshake());
+    try!(stream.inner().set_read_timeout(None));
+    try!(stream.inner().set_write_timeout(None));
+    Ok(stream)
+}
@@ -0,0 +1,131 @@
+//! OCSP status of the peer certificate, as stapled by the server.
+
+extern crate tls_sys as ffi;
+
+use chrono::datetime::DateTime;
+use chrono::offset::utc::UTC;
+use raw::TlsContext;
+
+/// Status of the OCSP response itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspResponseStatus {
+    Successful,
+    Malformed,
+    InternalError,
+    TryLater,
+    SigRequired,
+    Unauthorized,
+    /// A status value not known to this library
+    Other(i32),
+}
+
+impl OcspResponseStatus {
+    fn from_raw(status: i32) -> OcspResponseStatus {
+        match status {
+            ffi::OCSP_RESPONSE_SUCCESSFUL => OcspResponseStatus::Successful,
+            ffi::OCSP_RESPONSE_MALFORMED => OcspResponseStatus::Malformed,
+            ffi::OCSP_RESPONSE_INTERNALERROR => OcspResponseStatus::InternalError,
+            ffi::OCSP_RESPONSE_TRYLATER => OcspResponseStatus::TryLater,
+            ffi::OCSP_RESPONSE_SIGREQUIRED => OcspResponseStatus::SigRequired,
+            ffi::OCSP_RESPONSE_UNAUTHORIZED => OcspResponseStatus::Unauthorized,
+            other => OcspResponseStatus::Other(other),
+        }
+    }
+}
+
+/// Status of the certificate according to the OCSP responder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspCertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+impl OcspCertStatus {
+    fn from_raw(status: i32) -> Option<OcspCertStatus> {
+        match status {
+            ffi::OCSP_CERT_GOOD => Some(OcspCertStatus::Good),
+            ffi::OCSP_CERT_REVOKED => Some(OcspCertStatus::Revoked),
+            ffi::OCSP_CERT_UNKNOWN => Some(OcspCertStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// Reason a certificate was revoked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrlReason {
+    Unspecified,
+    KeyCompromise,
+    CaCompromise,
+    AffiliationChanged,
+    Superseded,
+    CessationOfOperation,
+    CertificateHold,
+    RemoveFromCrl,
+    PrivilegeWithdrawn,
+    AaCompromise,
+}
+
+impl CrlReason {
+    fn from_raw(reason: i32) -> Option<CrlReason> {
+        match reason {
+            ffi::CRL_REASON_UNSPECIFIED => Some(CrlReason::Unspecified),
+            ffi::CRL_REASON_KEY_COMPROMISE => Some(CrlReason::KeyCompromise),
+            ffi::CRL_REASON_CA_COMPROMISE => Some(CrlReason::CaCompromise),
+            ffi::CRL_REASON_AFFILIATION_CHANGED => Some(CrlReason::AffiliationChanged),
+            ffi::CRL_REASON_SUPERSEDED => Some(CrlReason::Superseded),
+            ffi::CRL_REASON_CESSATION_OF_OPERATION => Some(CrlReason::CessationOfOperation),
+            ffi::CRL_REASON_CERTIFICATE_HOLD => Some(CrlReason::CertificateHold),
+            ffi::CRL_REASON_REMOVE_FROM_CRL => Some(CrlReason::RemoveFromCrl),
+            ffi::CRL_REASON_PRIVILEGE_WITHDRAWN => Some(CrlReason::PrivilegeWithdrawn),
+            ffi::CRL_REASON_AA_COMPROMISE => Some(CrlReason::AaCompromise),
+            _ => None,
+        }
+    }
+}
+
+/// The OCSP response stapled by the peer, see `TlsStream::ocsp_status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcspStatus {
+    pub response_status: OcspResponseStatus,
+    /// None if the response was not successful
+    pub cert_status: Option<OcspCertStatus>,
+    /// Only set for revoked certificates
+    pub crl_reason: Option<CrlReason>,
+    /// Only set for revoked certificates
+    pub revocation_time: Option<DateTime<UTC>>,
+    pub this_update: Option<DateTime<UTC>>,
+    pub next_update: Option<DateTime<UTC>>,
+    /// Textual description of the result, from libtls
+    pub result: String,
+}
+
+impl OcspStatus {
+    /// The OCSP status of the peer, None if it did not staple a response
+    pub fn from_ctx(ctx: &TlsContext) -> Option<OcspStatus> {
+        let response_status = ctx.peer_ocsp_response_status();
+        if response_status == -1 {
+            return None;
+        }
+        let cert_status = OcspCertStatus::from_raw(ctx.peer_ocsp_cert_status());
+        let revoked = cert_status == Some(OcspCertStatus::Revoked);
+        Some(OcspStatus {
+            response_status: OcspResponseStatus::from_raw(response_status),
+            cert_status: cert_status,
+            crl_reason: if revoked {
+                CrlReason::from_raw(ctx.peer_ocsp_crl_reason())
+            } else {
+                None
+            },
+            revocation_time: if revoked {
+                ctx.peer_ocsp_revocation_time()
+            } else {
+                None
+            },
+            this_update: ctx.peer_ocsp_this_update(),
+            next_update: ctx.peer_ocsp_next_update(),
+            result: ctx.peer_ocsp_result(),
+        })
+    }
+}
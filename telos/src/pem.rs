@@ -0,0 +1,67 @@
+//! Splitting of PEM bundles into individual certificates.
+
+const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+const END: &[u8] = b"-----END CERTIFICATE-----";
+
+/// Iterator over the certificates in a PEM bundle, see `pem_certificates()`
+pub struct PemCertificates<'a> {
+    pem: &'a [u8],
+}
+
+/// Split a PEM bundle, such as the output of `TlsStream::peer_cert_chain_pem()`,
+/// into individual PEM certificates. Each item includes the BEGIN/END lines and
+/// the trailing newline, other content between certificates is skipped.
+pub fn pem_certificates(pem: &[u8]) -> PemCertificates<'_> {
+    PemCertificates { pem: pem }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+impl<'a> Iterator for PemCertificates<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let start = match find(self.pem, BEGIN) {
+            Some(pos) => pos,
+            None => {
+                self.pem = &[];
+                return None;
+            }
+        };
+        let mut end = match find(&self.pem[start..], END) {
+            Some(pos) => start + pos + END.len(),
+            None => {
+                // Truncated certificate
+                self.pem = &[];
+                return None;
+            }
+        };
+        if self.pem[end..].starts_with(b"\r\n") {
+            end += 2;
+        } else if self.pem[end..].starts_with(b"\n") {
+            end += 1;
+        }
+
+        let cert = &self.pem[start..end];
+        self.pem = &self.pem[end..];
+        Some(cert)
+    }
+}
+
+#[test]
+fn split_pem() {
+    let bundle = b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                   garbage\n\
+                   -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----";
+    let certs: Vec<&[u8]> = pem_certificates(bundle).collect();
+    assert_eq!(certs.len(), 2);
+    assert_eq!(certs[0],
+               &b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n"[..]);
+    assert_eq!(certs[1],
+               &b"-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----"[..]);
+
+    assert_eq!(pem_certificates(b"").count(), 0);
+    assert_eq!(pem_certificates(b"-----BEGIN CERTIFICATE-----\nAAAA\n").count(), 0);
+}
@@ -0,0 +1,406 @@
+//! Blocking TLS server running connections on a pool of threads.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream,
+               ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use super::{ServerBuilder, TlsServer, TlsStream};
+#[cfg(unix)]
+use super::Interest;
+use listener::accept_handshake;
+#[cfg(unix)]
+use timeout;
+
+/// How long `PoolHandle::shutdown()` waits for live connections
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the acceptor checks for shutdown while no connection arrives
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// A TLS server that accepts connections on its own thread and runs a handler
+/// for each connection on a fixed pool of worker threads
+///
+/// ```no_run
+/// use std::io::Write;
+/// use std::time::Duration;
+/// use telos::{PoolServer, TlsBuilder};
+///
+/// let server = telos::new_server()
+///     .key_file("tests/server.key")
+///     .cert_file("tests/server.crt");
+/// let pool = PoolServer::bind(server, "127.0.0.1:8443").unwrap()
+///     .threads(8)
+///     .max_connections(64)
+///     .handshake_timeout(Duration::from_secs(10))
+///     .spawn(|mut stream| { let _ = stream.write(b"hello"); })
+///     .unwrap();
+/// // ...
+/// pool.shutdown();
+/// ```
+///
+/// The TLS handshake is done on the worker thread, connections failing the
+/// handshake are dropped without calling the handler. A panic in the handler
+/// closes its connection, the worker keeps running.
+pub struct PoolServer {
+    server: TlsServer,
+    listener: TcpListener,
+    threads: usize,
+    max_connections: Option<usize>,
+    handshake_timeout: Option<Duration>,
+}
+
+impl PoolServer {
+    /// Create the TLS server from `builder` and listen on `addr`
+    pub fn bind<A: ToSocketAddrs>(builder: ServerBuilder, addr: A) -> io::Result<PoolServer> {
+        let server = try!(builder.bind());
+        let listener = try!(TcpListener::bind(addr));
+        Ok(PoolServer::new(server, listener))
+    }
+
+    /// Accept TLS connections from an existing TCP listener
+    pub fn new(server: TlsServer, listener: TcpListener) -> PoolServer {
+        PoolServer {
+            server: server,
+            listener: listener,
+            threads: 4,
+            max_connections: None,
+            handshake_timeout: None,
+        }
+    }
+
+    /// Number of worker threads, 4 by default. This is also the number of
+    /// connections handled at the same time.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = if threads == 0 { 1 } else { threads };
+        self
+    }
+
+    /// Maximum number of open connections, including the ones waiting for a
+    /// worker. Once reached no more connections are accepted until one closes.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Drop connections that do not complete the TLS handshake in time, see
+    /// `TlsStream::handshake_timeout()`
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Start accepting connections, `handler` is called with each connection once
+    /// its handshake is complete. The connection is closed when the handler returns.
+    pub fn spawn<F>(self, handler: F) -> io::Result<PoolHandle>
+        where F: Fn(TlsStream<TcpStream>) + Send + Sync + 'static
+    {
+        let addr = try!(self.listener.local_addr());
+        let shared = Arc::new(Shared {
+            conns: Mutex::new(Conns {
+                stopping: false,
+                next_id: 0,
+                live: HashMap::new(),
+            }),
+            closed: Condvar::new(),
+            max_connections: self.max_connections,
+        });
+        let server = Arc::new(self.server);
+        let handler = Arc::new(handler);
+        let (tx, rx) = mpsc::channel();
+        let rx = Arc::new(Mutex::new(rx));
+
+        let mut workers = Vec::with_capacity(self.threads);
+        for _ in 0..self.threads {
+            let worker = Worker {
+                shared: shared.clone(),
+                server: server.clone(),
+                jobs: rx.clone(),
+                handshake_timeout: self.handshake_timeout,
+            };
+            let handler = handler.clone();
+            workers.push(thread::spawn(move || worker.run(&*handler)));
+        }
+
+        let listener = self.listener;
+        try!(prepare_listener(&listener));
+        let acceptor_shared = shared.clone();
+        let acceptor = thread::spawn(move || {
+            loop {
+                if !acceptor_shared.wait_for_slot() {
+                    break;
+                }
+                let tcp = match accept(&listener) {
+                    Ok(Some(tcp)) => tcp,
+                    // Nothing to accept yet, check for shutdown
+                    Ok(None) => continue,
+                    Err(_) => {
+                        // e.g. out of file descriptors, give connections time to close
+                        thread::sleep(Duration::from_millis(50));
+                        continue;
+                    }
+                };
+                match acceptor_shared.register(&tcp) {
+                    Some(id) => {
+                        if tx.send((id, tcp)).is_err() {
+                            break;
+                        }
+                    }
+                    // Shutting down, or out of file descriptors: the loop
+                    // checks for shutdown, the connection is dropped
+                    None => continue,
+                }
+            }
+        });
+
+        Ok(PoolHandle {
+            shared: shared,
+            server: server,
+            addr: addr,
+            acceptor: acceptor,
+            workers: workers,
+        })
+    }
+}
+
+/// A running `PoolServer`, see `PoolServer::spawn()`
+pub struct PoolHandle {
+    shared: Arc<Shared>,
+    server: Arc<TlsServer>,
+    addr: SocketAddr,
+    acceptor: JoinHandle<()>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PoolHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The TLS server shared by the workers, e.g. to rotate its ticket keys with
+    /// `add_ticket_key()`
+    pub fn server(&self) -> &TlsServer {
+        &self.server
+    }
+
+    /// Number of open connections, including the ones waiting for a worker
+    pub fn connections(&self) -> usize {
+        self.shared.lock().live.len()
+    }
+
+    /// Stop accepting connections and wait up to 30 seconds for the live
+    /// connections to finish, see `shutdown_timeout()`.
+    pub fn shutdown(self) {
+        self.shutdown_timeout(SHUTDOWN_TIMEOUT)
+    }
+
+    /// Stop accepting connections and wait for the live connections to finish.
+    ///
+    /// The read side of every live connection is shut down, the handler sees the
+    /// end of stream and close_notify is sent when it returns. Connections still
+    /// open after `timeout` are closed without close_notify, their handlers fail
+    /// on the next read or write. A handler that does no I/O keeps the shutdown
+    /// waiting until it returns.
+    pub fn shutdown_timeout(self, timeout: Duration) {
+        {
+            let mut conns = self.shared.lock();
+            conns.stopping = true;
+            for tcp in conns.live.values() {
+                let _ = tcp.shutdown(Shutdown::Read);
+            }
+        }
+        self.shared.closed.notify_all();
+        // Wake up the acceptor if it is waiting for a connection, otherwise it
+        // notices the shutdown within ACCEPT_POLL
+        let _ = TcpStream::connect(wake_addr(self.addr));
+
+        if !self.shared.wait_closed(Instant::now() + timeout) {
+            for tcp in self.shared.lock().live.values() {
+                let _ = tcp.shutdown(Shutdown::Both);
+            }
+        }
+        let _ = self.acceptor.join();
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Get the listener ready for `accept()`
+#[cfg(unix)]
+fn prepare_listener(_: &TcpListener) -> io::Result<()> {
+    Ok(())
+}
+
+// Without poll() the listener is non-blocking, see accept()
+#[cfg(not(unix))]
+fn prepare_listener(listener: &TcpListener) -> io::Result<()> {
+    listener.set_nonblocking(true)
+}
+
+/// Accept a connection, None if there was none within ACCEPT_POLL
+#[cfg(unix)]
+fn accept(listener: &TcpListener) -> io::Result<Option<TcpStream>> {
+    if !try!(timeout::poll(listener.as_raw_fd(), Interest::Read, ACCEPT_POLL)) {
+        return Ok(None);
+    }
+    listener.accept().map(|(tcp, _)| Some(tcp))
+}
+
+#[cfg(not(unix))]
+fn accept(listener: &TcpListener) -> io::Result<Option<TcpStream>> {
+    match listener.accept() {
+        Ok((tcp, _)) => {
+            // Accepted sockets may inherit the non-blocking mode
+            try!(tcp.set_nonblocking(false));
+            Ok(Some(tcp))
+        }
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+            thread::sleep(ACCEPT_POLL);
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// An address that reaches a listener bound to `addr`
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), addr.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), addr.port())
+        }
+        _ => addr,
+    }
+}
+
+struct Conns {
+    stopping: bool,
+    next_id: usize,
+    /// Open connections, a clone of each TCP stream is kept for shutdown
+    live: HashMap<usize, TcpStream>,
+}
+
+struct Shared {
+    conns: Mutex<Conns>,
+    /// Signalled when a connection closes or on shutdown
+    closed: Condvar,
+    max_connections: Option<usize>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Conns> {
+        self.conns.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Wait until all connections are closed, false if `deadline` passed first
+    fn wait_closed(&self, deadline: Instant) -> bool {
+        let mut conns = self.lock();
+        while !conns.live.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            conns = match self.closed.wait_timeout(conns, deadline - now) {
+                Ok((conns, _)) => conns,
+                Err(err) => err.into_inner().0,
+            };
+        }
+        true
+    }
+
+    /// Wait until another connection can be accepted, false on shutdown
+    fn wait_for_slot(&self) -> bool {
+        let mut conns = self.lock();
+        loop {
+            if conns.stopping {
+                return false;
+            }
+            match self.max_connections {
+                Some(max) if conns.live.len() >= max => {
+                    conns = self.closed.wait(conns).unwrap_or_else(|err| err.into_inner());
+                }
+                _ => return true,
+            }
+        }
+    }
+
+    /// Track a new connection, None on shutdown or if the connection can't be
+    /// tracked, it is then dropped
+    fn register(&self, tcp: &TcpStream) -> Option<usize> {
+        let clone = match tcp.try_clone() {
+            Ok(clone) => clone,
+            Err(_) => return None,
+        };
+        let mut conns = self.lock();
+        if conns.stopping {
+            return None;
+        }
+        let id = conns.next_id;
+        conns.next_id = conns.next_id.wrapping_add(1);
+        conns.live.insert(id, clone);
+        Some(id)
+    }
+
+    fn stopping(&self) -> bool {
+        self.lock().stopping
+    }
+}
+
+/// Removes a connection from `Shared`, even if the handler panics
+struct Registered<'a> {
+    shared: &'a Shared,
+    id: usize,
+}
+
+impl<'a> Drop for Registered<'a> {
+    fn drop(&mut self) {
+        self.shared.lock().live.remove(&self.id);
+        self.shared.closed.notify_all();
+    }
+}
+
+struct Worker {
+    shared: Arc<Shared>,
+    server: Arc<TlsServer>,
+    jobs: Arc<Mutex<Receiver<(usize, TcpStream)>>>,
+    handshake_timeout: Option<Duration>,
+}
+
+impl Worker {
+    fn run<F: Fn(TlsStream<TcpStream>)>(&self, handler: &F) {
+        loop {
+            let job = {
+                let jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+                jobs.recv()
+            };
+            let (id, tcp) = match job {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+            let _registered = Registered {
+                shared: &self.shared,
+                id: id,
+            };
+            if self.shared.stopping() {
+                continue;
+            }
+            if let Ok(stream) = accept_handshake(&self.server, tcp, self.handshake_timeout) {
+                // A panicking handler only loses its connection, not the worker
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(stream)));
+            }
+        }
+    }
+}
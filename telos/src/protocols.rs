@@ -0,0 +1,136 @@
+//! TLS protocol versions, see `ClientBuilder::protocols()`.
+
+extern crate tls_sys as ffi;
+
+use std::ffi::CString;
+use std::fmt;
+use std::str::FromStr;
+use raw::{TlsError, TlsErrorKind};
+
+bitflags! {
+    /// A set of TLS protocol versions
+    pub struct Protocols: u32 {
+        const TLSV1_0 = ffi::PROTOCOL_TLSV1_0;
+        const TLSV1_1 = ffi::PROTOCOL_TLSV1_1;
+        const TLSV1_2 = ffi::PROTOCOL_TLSV1_2;
+        const TLSV1_3 = ffi::PROTOCOL_TLSV1_3;
+        /// All TLSv1 versions
+        const TLSV1 = Self::TLSV1_0.bits | Self::TLSV1_1.bits | Self::TLSV1_2.bits |
+                      Self::TLSV1_3.bits;
+        /// The versions libtls considers secure, the libtls default
+        const SECURE = Self::TLSV1_2.bits | Self::TLSV1_3.bits;
+        const DEFAULT = Self::SECURE.bits;
+        /// All versions, including the ones that are no longer considered secure
+        const LEGACY = Self::TLSV1.bits;
+        const ALL = Self::TLSV1.bits;
+    }
+}
+
+impl Default for Protocols {
+    fn default() -> Protocols {
+        Protocols::DEFAULT
+    }
+}
+
+/// Parse the libtls protocol string form, e.g. `"secure"` or `"tlsv1.2,tlsv1.3"`
+impl FromStr for Protocols {
+    type Err = TlsError;
+
+    fn from_str(s: &str) -> Result<Protocols, TlsError> {
+        let mut proto = 0;
+        let rv = match CString::new(s) {
+            Ok(s_c) => unsafe { ffi::tls_config_parse_protocols(&mut proto, s_c.as_ptr()) },
+            Err(_) => -1,
+        };
+        if rv == -1 {
+            Err(TlsError::with_kind(TlsErrorKind::Config, format!("Invalid protocols: {}", s)))
+        } else {
+            Ok(Protocols::from_bits_truncate(proto))
+        }
+    }
+}
+
+/// The libtls protocol string form, e.g. `"tlsv1.2,tlsv1.3"`
+impl fmt::Display for Protocols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = Protocol::all()
+            .iter()
+            .filter(|p| self.contains(p.flag()))
+            .map(|p| p.name())
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// A single TLS protocol version, see `TlsStream::protocol_version()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    Tlsv1_0,
+    Tlsv1_1,
+    Tlsv1_2,
+    Tlsv1_3,
+}
+
+impl Protocol {
+    fn all() -> [Protocol; 4] {
+        [Protocol::Tlsv1_0, Protocol::Tlsv1_1, Protocol::Tlsv1_2, Protocol::Tlsv1_3]
+    }
+
+    /// The `Protocols` set containing only this version
+    pub fn flag(&self) -> Protocols {
+        match *self {
+            Protocol::Tlsv1_0 => Protocols::TLSV1_0,
+            Protocol::Tlsv1_1 => Protocols::TLSV1_1,
+            Protocol::Tlsv1_2 => Protocols::TLSV1_2,
+            Protocol::Tlsv1_3 => Protocols::TLSV1_3,
+        }
+    }
+
+    /// Name in the libtls protocol string form
+    fn name(&self) -> &'static str {
+        match *self {
+            Protocol::Tlsv1_0 => "tlsv1.0",
+            Protocol::Tlsv1_1 => "tlsv1.1",
+            Protocol::Tlsv1_2 => "tlsv1.2",
+            Protocol::Tlsv1_3 => "tlsv1.3",
+        }
+    }
+
+    /// Parse the version string reported by libtls for a connection, e.g. `"TLSv1.2"`
+    pub fn from_conn_version(version: &str) -> Option<Protocol> {
+        match version {
+            "TLSv1" | "TLSv1.0" => Some(Protocol::Tlsv1_0),
+            "TLSv1.1" => Some(Protocol::Tlsv1_1),
+            "TLSv1.2" => Some(Protocol::Tlsv1_2),
+            "TLSv1.3" => Some(Protocol::Tlsv1_3),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[test]
+fn protocols_roundtrip() {
+    for s in &["all", "legacy", "default", "secure", "tlsv1", "tlsv1.0", "tlsv1.2,tlsv1.3"] {
+        let protocols: Protocols = s.parse().unwrap();
+        assert_eq!(protocols.to_string().parse::<Protocols>().unwrap(), protocols);
+    }
+    assert_eq!("secure".parse::<Protocols>().unwrap(), Protocols::SECURE);
+    assert_eq!("all".parse::<Protocols>().unwrap(), Protocols::ALL);
+    assert_eq!("tlsv1.1".parse::<Protocols>().unwrap(), Protocols::TLSV1_1);
+    assert_eq!((Protocols::TLSV1_0 | Protocols::TLSV1_3).to_string(), "tlsv1.0,tlsv1.3");
+    assert!("unknown-proto".parse::<Protocols>().is_err());
+}
+
+#[test]
+fn protocol_from_conn_version() {
+    assert_eq!(Protocol::from_conn_version("TLSv1"), Some(Protocol::Tlsv1_0));
+    assert_eq!(Protocol::from_conn_version("TLSv1.3"), Some(Protocol::Tlsv1_3));
+    assert_eq!(Protocol::from_conn_version(""), None);
+    assert_eq!(Protocol::from_conn_version("SSLv3"), None);
+}
@@ -6,48 +6,109 @@ extern crate tls_sys as ffi;
 extern crate libc;
 
 use std::ffi::CString;
-use libc::{c_void, size_t};
+#[cfg(unix)]
+use std::fs::File;
+use libc::{c_void, size_t, ssize_t};
 use std::ptr;
+use std::slice;
+use std::any::Any;
 use std::error::Error;
 use std::fmt;
 use std::convert;
 use std::io;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 #[cfg(unix)]
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 #[cfg(windows)]
 use std::os::windows::io::RawSocket;
 use std::sync::{Once, ONCE_INIT};
 use super::util::*;
+use super::protocols::Protocols;
 use chrono::datetime::DateTime;
 use chrono::naive::datetime::NaiveDateTime;
 use chrono::offset::utc::UTC;
 
+/// The kind of failure behind a `TlsError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsErrorKind {
+    /// Invalid settings, or settings libtls failed to apply
+    Config,
+    /// The TLS handshake failed
+    Handshake,
+    /// The peer certificate could not be verified
+    Verify,
+    /// The peer certificate is not valid for the requested name
+    NameMismatch,
+    /// The peer certificate was revoked, see `TlsBuilder::crl_file`
+    Revoked,
+    /// The peer certificate hash matches none of the pinned hashes
+    PinMismatch,
+    /// The connection was already closed
+    Closed,
+    /// The operation did not complete in time, see `TlsStream::set_read_timeout`
+    Timeout,
+    /// The operation would block until the socket is readable (`TLS_WANT_POLLIN`)
+    WantPollIn,
+    /// The operation would block until the socket is writable (`TLS_WANT_POLLOUT`)
+    WantPollOut,
+    /// An I/O error, with the OS error number if there is one
+    Io(Option<i32>),
+    Other,
+}
+
 #[derive(Debug)]
 pub struct TlsError {
+    kind: TlsErrorKind,
     msg: String,
-    code: i64,
+    io: Option<io::Error>,
 }
 
 impl TlsError {
     /// The operation failed because it would block reading
-    fn want_pollin(&self) -> bool {
-        self.code == ffi::WANT_POLLIN
+    pub fn want_pollin(&self) -> bool {
+        self.kind == TlsErrorKind::WantPollIn
     }
     /// The operation failed because it would block writing
-    fn want_pollout(&self) -> bool {
-        self.code == ffi::WANT_POLLIN
+    pub fn want_pollout(&self) -> bool {
+        self.kind == TlsErrorKind::WantPollOut
     }
     /// The operation failed because it would block, repeating
     /// the same operation should succeed, but will block.
     pub fn wants_more(&self) -> bool {
         self.want_pollin() || self.want_pollout()
     }
+    /// The readiness to wait for before repeating the operation, None
+    /// if the operation failed for some other reason
+    pub fn interest(&self) -> Option<Interest> {
+        match self.kind {
+            TlsErrorKind::WantPollIn => Some(Interest::Read),
+            TlsErrorKind::WantPollOut => Some(Interest::Write),
+            _ => None,
+        }
+    }
     pub fn new<S: Into<String>>(msg: S) -> TlsError {
+        TlsError::with_kind(TlsErrorKind::Other, msg)
+    }
+    pub fn with_kind<S: Into<String>>(kind: TlsErrorKind, msg: S) -> TlsError {
         TlsError {
+            kind: kind,
             msg: msg.into(),
-            code: -1,
+            io: None,
         }
     }
+    fn config<S: Into<String>>(msg: S) -> TlsError {
+        TlsError::with_kind(TlsErrorKind::Config, msg)
+    }
+    pub fn kind(&self) -> TlsErrorKind {
+        self.kind
+    }
+    /// The I/O error raised by a callback stream, if this error was caused by one
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.io.as_ref()
+    }
 }
 
 impl fmt::Display for TlsError {
@@ -60,31 +121,145 @@ impl Error for TlsError {
         &self.msg
     }
 }
-/// Convert TlsError to io::Error, use WouldBlock if applicable
+
+/// Wrap an I/O error, e.g. from a socket call made on behalf of libtls
+impl convert::From<io::Error> for TlsError {
+    fn from(err: io::Error) -> Self {
+        TlsError {
+            kind: TlsErrorKind::Io(err.raw_os_error()),
+            msg: err.to_string(),
+            io: Some(err),
+        }
+    }
+}
+
+/// Convert TlsError to io::Error, with the `io::ErrorKind` closest to the
+/// `TlsErrorKind`. Errors raised by a callback stream are returned as they were.
 impl convert::From<TlsError> for io::Error {
     fn from(err: TlsError) -> Self {
-        match err.code {
-            ffi::WANT_POLLIN | ffi::WANT_POLLOUT => io::Error::new(io::ErrorKind::WouldBlock, err),
-            _ => io::Error::new(io::ErrorKind::Other, err.msg),
+        if let Some(io) = err.io {
+            return io;
         }
+        let kind = match err.kind {
+            TlsErrorKind::WantPollIn |
+            TlsErrorKind::WantPollOut => io::ErrorKind::WouldBlock,
+            TlsErrorKind::Config => io::ErrorKind::InvalidInput,
+            TlsErrorKind::Handshake => io::ErrorKind::ConnectionAborted,
+            TlsErrorKind::Verify |
+            TlsErrorKind::NameMismatch |
+            TlsErrorKind::Revoked |
+            TlsErrorKind::PinMismatch => io::ErrorKind::InvalidData,
+            TlsErrorKind::Closed => io::ErrorKind::NotConnected,
+            TlsErrorKind::Timeout => io::ErrorKind::TimedOut,
+            TlsErrorKind::Io(Some(errno)) => io::Error::from_raw_os_error(errno).kind(),
+            TlsErrorKind::Io(None) |
+            TlsErrorKind::Other => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
     }
 }
 
+/// Classify a libtls failure from its message. libtls keeps its `SSL` handle to
+/// itself, so the X509 verify result is out of reach: errors caused by a failed
+/// system call are recognised by the `strerror(errno)` libtls appends, and
+/// verification failures by the fixed wording libtls uses for them. Anything
+/// else is `default`.
+fn classify(msg: &str, errno: Option<i32>, default: TlsErrorKind) -> TlsErrorKind {
+    if let Some(errno) = errno {
+        if errno != 0 {
+            let reason = unsafe { from_cstr(libc::strerror(errno)) };
+            if !reason.is_empty() && msg.ends_with(&reason) {
+                return TlsErrorKind::Io(Some(errno));
+            }
+        }
+    }
+    if msg.contains("not present in") {
+        return TlsErrorKind::NameMismatch;
+    }
+    if msg.contains("certificate revoked") {
+        return TlsErrorKind::Revoked;
+    }
+    if msg.contains("certificate verification failed") || msg.contains("certificate verify failed") {
+        return TlsErrorKind::Verify;
+    }
+    if msg.starts_with("handshake failed") {
+        return TlsErrorKind::Handshake;
+    }
+    default
+}
+
 /// result type for TLS operations
 pub type TlsResult<T> = Result<T, TlsError>;
 
+/// The socket readiness a non-blocking operation is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+    /// Retry once the socket is readable (`TLS_WANT_POLLIN`)
+    Read,
+    /// Retry once the socket is writable (`TLS_WANT_POLLOUT`)
+    Write,
+}
+
+/// The outcome of a non-blocking operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status<T> {
+    /// The operation completed
+    Ready(T),
+    /// The operation would block, repeat it once the socket has the given readiness
+    Blocked(Interest),
+}
+
+impl<T> Status<T> {
+    /// Turn `TLS_WANT_POLLIN`/`TLS_WANT_POLLOUT` errors into `Status::Blocked`
+    pub fn from_result(res: TlsResult<T>) -> TlsResult<Status<T>> {
+        match res {
+            Ok(val) => Ok(Status::Ready(val)),
+            Err(err) => {
+                match err.interest() {
+                    Some(interest) => Ok(Status::Blocked(interest)),
+                    None => Err(err),
+                }
+            }
+        }
+    }
+}
+
 /// TLS configuration settings, see `TlsContext::configure` to apply them
 pub struct TlsConfig {
     cfg: ffi::Config,
+    /// Session file, must stay open while the config is in use
+    #[cfg(unix)]
+    session: Option<File>,
 }
 
 impl TlsConfig {
     pub fn new() -> TlsResult<TlsConfig> {
         let p = unsafe { ffi::tls_config_new() };
         if p == ptr::null_mut() {
-            Err(TlsError::new("Unable to create TLS config"))
+            Err(TlsError::config("Unable to create TLS config"))
         } else {
-            Ok(TlsConfig { cfg: p })
+            Ok(TlsConfig {
+                cfg: p,
+                #[cfg(unix)]
+                session: None,
+            })
+        }
+    }
+
+    /// The last error reported by libtls for this config
+    pub fn error(&self) -> String {
+        unsafe { from_cstr(ffi::tls_config_error(self.cfg)) }
+    }
+
+    /// Error for a failed setting, `what` names the setting (and value) that
+    /// failed followed by the libtls diagnostic
+    fn last_error<S: Into<String>>(&self, what: S) -> TlsError {
+        let what = what.into();
+        let reason = self.error();
+        if reason.is_empty() {
+            TlsError::config(what)
+        } else {
+            TlsError::config(format!("{}: {}", what, reason))
         }
     }
 
@@ -96,7 +271,7 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError::new("Unable to set CA file"))
+            Err(self.last_error(format!("Unable to set CA file {}", path)))
         }
     }
     pub fn set_ca_path(&mut self, path: &str) -> TlsResult<()> {
@@ -107,7 +282,7 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError::new("Unable to set CA path"))
+            Err(self.last_error(format!("Unable to set CA path {}", path)))
         }
     }
     pub fn set_ca_mem(&mut self, ca: &str) -> TlsResult<()> {
@@ -115,7 +290,29 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError::new("Unable to set CA from memory"))
+            Err(self.last_error("Unable to set CA from memory"))
+        }
+    }
+    /// Load certificate revocation lists from PEM file, the peer certificate
+    /// is rejected if it was revoked
+    pub fn set_crl_file(&mut self, path: &str) -> TlsResult<()> {
+        let rv = unsafe {
+            let path_c = CString::from_vec_unchecked(path.bytes().collect());
+            ffi::tls_config_set_crl_file(self.cfg, path_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Unable to set CRL file {}", path)))
+        }
+    }
+    /// Use certificate revocation lists from PEM in memory
+    pub fn set_crl_mem(&mut self, crl: &[u8]) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_crl_mem(self.cfg, crl.as_ptr(), crl.len()) };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set CRL from memory"))
         }
     }
     pub fn set_verify_depth(&mut self, depth: i32) {
@@ -127,6 +324,23 @@ impl TlsConfig {
     pub fn insecure_noverifycert(&mut self) {
         unsafe { ffi::tls_config_insecure_noverifycert(self.cfg) }
     }
+    /// Choose the cipher using the server's preferences
+    pub fn prefer_ciphers_server(&mut self) {
+        unsafe { ffi::tls_config_prefer_ciphers_server(self.cfg) }
+    }
+    /// Choose the cipher using the client's preferences
+    pub fn prefer_ciphers_client(&mut self) {
+        unsafe { ffi::tls_config_prefer_ciphers_client(self.cfg) }
+    }
+    /// Require clients to present a valid certificate (server-side)
+    pub fn verify_client(&mut self) {
+        unsafe { ffi::tls_config_verify_client(self.cfg) }
+    }
+    /// Verify client certificates if they are presented, but allow clients
+    /// without a certificate (server-side)
+    pub fn verify_client_optional(&mut self) {
+        unsafe { ffi::tls_config_verify_client_optional(self.cfg) }
+    }
     pub fn set_key_file(&mut self, path: &str) -> TlsResult<()> {
         let rv = unsafe {
             let path_c = CString::from_vec_unchecked(path.bytes().collect());
@@ -135,7 +349,7 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError::new("Unable to set key file"))
+            Err(self.last_error(format!("Unable to set key file {}", path)))
         }
     }
     pub fn set_cert_file(&mut self, path: &str) -> TlsResult<()> {
@@ -146,19 +360,127 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError::new("Unable to set certificate file"))
+            Err(self.last_error(format!("Unable to set certificate file {}", path)))
+        }
+    }
+    pub fn set_key_mem(&mut self, key: &[u8]) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_key_mem(self.cfg, key.as_ptr(), key.len()) };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set key from memory"))
+        }
+    }
+    pub fn set_cert_mem(&mut self, cert: &[u8]) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_cert_mem(self.cfg, cert.as_ptr(), cert.len()) };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set certificate from memory"))
+        }
+    }
+    pub fn set_keypair_mem(&mut self, cert: &[u8], key: &[u8]) -> TlsResult<()> {
+        let rv = unsafe {
+            ffi::tls_config_set_keypair_mem(self.cfg,
+                                            cert.as_ptr(),
+                                            cert.len(),
+                                            key.as_ptr(),
+                                            key.len())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set keypair from memory"))
+        }
+    }
+    /// Add an additional keypair, selected when clients request its name via SNI
+    pub fn add_keypair_file(&mut self, cert_file: &str, key_file: &str) -> TlsResult<()> {
+        let rv = unsafe {
+            let cert_c = CString::from_vec_unchecked(cert_file.bytes().collect());
+            let key_c = CString::from_vec_unchecked(key_file.bytes().collect());
+            ffi::tls_config_add_keypair_file(self.cfg, cert_c.as_ptr(), key_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Unable to add keypair {}, {}", cert_file, key_file)))
+        }
+    }
+    /// Add an additional keypair from memory, see `add_keypair_file()`
+    pub fn add_keypair_mem(&mut self, cert: &[u8], key: &[u8]) -> TlsResult<()> {
+        let rv = unsafe {
+            ffi::tls_config_add_keypair_mem(self.cfg,
+                                            cert.as_ptr(),
+                                            cert.len(),
+                                            key.as_ptr(),
+                                            key.len())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to add keypair from memory"))
+        }
+    }
+    /// Use `file` to store the client session, so later connections can resume it.
+    /// The file must not be accessible by group or others.
+    #[cfg(unix)]
+    pub fn set_session_file(&mut self, file: File) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_session_fd(self.cfg, file.as_raw_fd()) };
+        if rv == 0 {
+            self.session = Some(file);
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set session file"))
+        }
+    }
+    /// Whether a session file was set with `set_session_file()`
+    #[cfg(unix)]
+    pub fn has_session_file(&self) -> bool {
+        self.session.is_some()
+    }
+    /// Session ID for the server session cache, at most `ffi::MAX_SESSION_ID_LENGTH`
+    /// bytes. Servers sharing a session cache must use the same ID.
+    pub fn set_session_id(&mut self, id: &[u8]) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_session_id(self.cfg, id.as_ptr(), id.len() as size_t) };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Invalid session id"))
+        }
+    }
+    /// Lifetime of server sessions and tickets in seconds, 0 disables the
+    /// session cache
+    pub fn set_session_lifetime(&mut self, seconds: i32) -> TlsResult<()> {
+        let rv = unsafe { ffi::tls_config_set_session_lifetime(self.cfg, seconds) };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Invalid session lifetime {}", seconds)))
+        }
+    }
+    /// Add a session ticket key (`ffi::TICKET_KEY_SIZE` bytes) with revision
+    /// `keyrev`. The newest key encrypts new tickets, older keys are kept to
+    /// decrypt tickets until the session lifetime expires.
+    pub fn add_ticket_key(&mut self, keyrev: u32, key: &[u8]) -> TlsResult<()> {
+        let mut key = key.to_vec();
+        let rv = unsafe {
+            ffi::tls_config_add_ticket_key(self.cfg, keyrev, key.as_mut_ptr(), key.len() as size_t)
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Unable to add ticket key {}", keyrev)))
         }
     }
-    pub fn set_protocols(&mut self, protocols: &str) -> TlsResult<()> {
-        let mut proto = 0;
+    /// Zero and release the private keys held by the config. Call this after
+    /// the config was applied to a server context, the keys are no longer needed.
+    pub fn clear_keys(&mut self) {
+        unsafe { ffi::tls_config_clear_keys(self.cfg) }
+    }
+    pub fn set_protocols(&mut self, protocols: Protocols) {
         unsafe {
-            let proto_c = CString::from_vec_unchecked(protocols.bytes().collect());
-            if ffi::tls_config_parse_protocols(&mut proto, proto_c.as_ptr()) == -1 {
-                return Err(TlsError::new(format!("Invalid protocols: {}", protocols)));
-            }
-            ffi::tls_config_set_protocols(self.cfg, proto);
+            ffi::tls_config_set_protocols(self.cfg, protocols.bits());
         }
-        Ok(())
     }
     pub fn set_ciphers(&mut self, ciphers: &str) -> TlsResult<()> {
         let rv = unsafe {
@@ -168,7 +490,72 @@ impl TlsConfig {
         if rv == 0 {
             Ok(())
         } else {
-            return Err(TlsError::new(format!("Invalid ciphers: {}", ciphers)));
+            return Err(self.last_error(format!("Invalid ciphers {}", ciphers)));
+        }
+    }
+    /// Set the curves for ECDHE key exchange, a comma separated list of
+    /// names such as `"X25519,P-256"`, or `"default"`
+    pub fn set_ecdhecurves(&mut self, curves: &str) -> TlsResult<()> {
+        let rv = unsafe {
+            let curves_c = CString::from_vec_unchecked(curves.bytes().collect());
+            ffi::tls_config_set_ecdhecurves(self.cfg, curves_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Invalid ECDHE curves {}", curves)))
+        }
+    }
+    /// Set the DHE parameters, one of `"none"`, `"auto"` or `"legacy"`
+    pub fn set_dheparams(&mut self, params: &str) -> TlsResult<()> {
+        let rv = unsafe {
+            let params_c = CString::from_vec_unchecked(params.bytes().collect());
+            ffi::tls_config_set_dheparams(self.cfg, params_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Invalid DHE params {}", params)))
+        }
+    }
+    /// Staple the OCSP response in `path` (DER) to the server certificate
+    pub fn set_ocsp_staple_file(&mut self, path: &str) -> TlsResult<()> {
+        let rv = unsafe {
+            let path_c = CString::from_vec_unchecked(path.bytes().collect());
+            ffi::tls_config_set_ocsp_staple_file(self.cfg, path_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Unable to set OCSP staple file {}", path)))
+        }
+    }
+    /// Staple the OCSP response (DER) to the server certificate
+    pub fn set_ocsp_staple_mem(&mut self, staple: &[u8]) -> TlsResult<()> {
+        let rv = unsafe {
+            ffi::tls_config_set_ocsp_staple_mem(self.cfg, staple.as_ptr(), staple.len())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error("Unable to set OCSP staple from memory"))
+        }
+    }
+    /// Fail the handshake unless the server staples a valid OCSP response
+    pub fn ocsp_require_stapling(&mut self) {
+        unsafe { ffi::tls_config_ocsp_require_stapling(self.cfg) }
+    }
+    /// Set the ALPN protocols, in order of preference
+    pub fn set_alpn(&mut self, protocols: &[&str]) -> TlsResult<()> {
+        let alpn = protocols.join(",");
+        let rv = unsafe {
+            let alpn_c = CString::from_vec_unchecked(alpn.bytes().collect());
+            ffi::tls_config_set_alpn(self.cfg, alpn_c.as_ptr())
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(self.last_error(format!("Invalid ALPN protocols {}", alpn)))
         }
     }
 }
@@ -181,12 +568,166 @@ impl Drop for TlsConfig {
     }
 }
 
+// The config is only changed through &mut self. Configuring contexts with a
+// shared config only updates the libtls reference count, which libtls protects
+// with its own lock.
+unsafe impl Send for TlsConfig {}
+unsafe impl Sync for TlsConfig {}
+
+/// State shared between a `CallbackStream` and the libtls I/O callbacks
+struct CallbackState<S> {
+    stream: S,
+    error: Option<io::Error>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+/// Errors captured by the I/O callbacks, without the stream type
+trait CallbackErrors {
+    fn take_error(&self) -> Option<io::Error>;
+    fn take_panic(&self) -> Option<Box<dyn Any + Send>>;
+}
+
+impl<S> CallbackErrors for Mutex<CallbackState<S>> {
+    fn take_error(&self) -> Option<io::Error> {
+        lock_state(self).error.take()
+    }
+    fn take_panic(&self) -> Option<Box<dyn Any + Send>> {
+        lock_state(self).panic.take()
+    }
+}
+
+/// Panics in the stream are caught inside the callbacks, the lock can only be
+/// poisoned by a panic while the stream was borrowed elsewhere.
+fn lock_state<S>(state: &Mutex<CallbackState<S>>) -> MutexGuard<'_, CallbackState<S>> {
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Call `f` on the stream from inside a libtls callback. `WouldBlock` is
+/// reported to libtls as `want`, other errors and panics are kept in the
+/// state until the libtls call returns.
+fn callback_io<S, F>(state: &Mutex<CallbackState<S>>, want: i64, mut f: F) -> ssize_t
+    where F: FnMut(&mut S) -> io::Result<usize>
+{
+    let mut guard = match state.try_lock() {
+        Ok(guard) => guard,
+        Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        // The stream is borrowed through CallbackStream::get_mut()
+        Err(TryLockError::WouldBlock) => return -1,
+    };
+    let state = &mut *guard;
+    let stream = &mut state.stream;
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        loop {
+            match f(stream) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                res => return res,
+            }
+        }
+    }));
+    match res {
+        Ok(Ok(len)) => len as ssize_t,
+        Ok(Err(ref err)) if err.kind() == io::ErrorKind::WouldBlock => want as ssize_t,
+        Ok(Err(err)) => {
+            state.error = Some(err);
+            -1
+        }
+        Err(payload) => {
+            state.panic = Some(payload);
+            -1
+        }
+    }
+}
+
+extern "C" fn read_cb<S: Read>(_ctx: ffi::Tls,
+                               buf: *mut c_void,
+                               buflen: size_t,
+                               cb_arg: *mut c_void)
+                               -> ssize_t {
+    let state = unsafe { &*(cb_arg as *const Mutex<CallbackState<S>>) };
+    let buf = unsafe { slice::from_raw_parts_mut(buf as *mut u8, buflen) };
+    callback_io(state, ffi::WANT_POLLIN, |stream| stream.read(buf))
+}
+
+extern "C" fn write_cb<S: Write>(_ctx: ffi::Tls,
+                                 buf: *const c_void,
+                                 buflen: size_t,
+                                 cb_arg: *mut c_void)
+                                 -> ssize_t {
+    let state = unsafe { &*(cb_arg as *const Mutex<CallbackState<S>>) };
+    let buf = unsafe { slice::from_raw_parts(buf as *const u8, buflen) };
+    callback_io(state, ffi::WANT_POLLOUT, |stream| stream.write(buf))
+}
+
+/// A Rust stream that libtls reads and writes through callbacks, see
+/// `TlsContext::connect_cbs()` and `TlsContext::accept_cbs()`.
+///
+/// A `WouldBlock` error from the stream is reported as `TLS_WANT_POLLIN`/`TLS_WANT_POLLOUT`,
+/// other errors are returned by the libtls call that triggered them (see
+/// `TlsError::io_error()`), and panics are resumed once the libtls call returns.
+pub struct CallbackStream<S> {
+    state: Arc<Mutex<CallbackState<S>>>,
+}
+
+impl<S: Read + Write + 'static> CallbackStream<S> {
+    pub fn new(stream: S) -> CallbackStream<S> {
+        CallbackStream {
+            state: Arc::new(Mutex::new(CallbackState {
+                stream: stream,
+                error: None,
+                panic: None,
+            })),
+        }
+    }
+
+    fn cb_arg(&self) -> *mut c_void {
+        &*self.state as *const Mutex<CallbackState<S>> as *mut c_void
+    }
+}
+
+impl<S> CallbackStream<S> {
+    /// Borrow the inner stream. While the borrow is alive any libtls call
+    /// that needs to do I/O on the stream will fail.
+    pub fn get_ref(&self) -> StreamGuard<'_, S> {
+        StreamGuard { guard: lock_state(&self.state) }
+    }
+    /// Mutably borrow the inner stream, see `get_ref()`.
+    pub fn get_mut(&mut self) -> StreamGuard<'_, S> {
+        StreamGuard { guard: lock_state(&self.state) }
+    }
+}
+
+/// A borrow of the stream inside a `CallbackStream`
+pub struct StreamGuard<'a, S: 'a> {
+    guard: MutexGuard<'a, CallbackState<S>>,
+}
+
+impl<'a, S> Deref for StreamGuard<'a, S> {
+    type Target = S;
+    fn deref(&self) -> &S {
+        &self.guard.stream
+    }
+}
+
+impl<'a, S> DerefMut for StreamGuard<'a, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.guard.stream
+    }
+}
+
 /// A structure that represents all TLS context
 ///
 /// This can be a client connection, a server, or a connection accepted by the server
 pub struct TlsContext {
     ptr: ffi::Tls,
-    cfg: Option<TlsConfig>,
+    cfg: Option<Arc<TlsConfig>>,
+    cbs: Option<Arc<dyn CallbackErrors>>,
+    closed: bool,
+    /// Accepted peer certificate hashes, see `set_pins()`
+    pins: Vec<String>,
+    /// Outcome of the pin check, None until the handshake is complete
+    pin_check: Option<bool>,
+    /// The server context a connection was accepted from, see `set_server()`
+    server: Option<Arc<ServerContext>>,
 }
 
 impl TlsContext {
@@ -199,6 +740,11 @@ impl TlsContext {
             Ok(TlsContext {
                 ptr: p,
                 cfg: None,
+                cbs: None,
+                closed: false,
+                pins: Vec::new(),
+                pin_check: None,
+                server: None,
             })
         }
     }
@@ -207,14 +753,48 @@ impl TlsContext {
         unsafe { from_cstr(ffi::tls_error(self.ptr)) }
     }
 
-    fn rv_to_result(&self, rv: i64) -> TlsResult<()> {
+    /// Resume a panic raised inside the I/O callbacks, and pick up any I/O
+    /// error they stored
+    fn callback_error(&self) -> Option<io::Error> {
+        match self.cbs {
+            Some(ref cbs) => {
+                if let Some(payload) = cbs.take_panic() {
+                    panic::resume_unwind(payload);
+                }
+                cbs.take_error()
+            }
+            None => None,
+        }
+    }
+
+    /// Build the error for a failed libtls call, `errno` must be captured right
+    /// after the call
+    fn last_error(&self, errno: Option<i32>, io: Option<io::Error>, default: TlsErrorKind) -> TlsError {
+        let libtls_msg = self.error();
+        let kind = match io {
+            Some(ref io) => TlsErrorKind::Io(io.raw_os_error()),
+            None if self.closed => TlsErrorKind::Closed,
+            None if self.peer_ocsp_cert_status() == ffi::OCSP_CERT_REVOKED => TlsErrorKind::Revoked,
+            None => classify(&libtls_msg, errno, default),
+        };
+        let msg = match io {
+            Some(ref io) => format!("{}: {}", libtls_msg, io),
+            None => libtls_msg,
+        };
+        TlsError {
+            kind: kind,
+            msg: msg,
+            io: io,
+        }
+    }
+
+    fn rv_to_result(&self, rv: i64, default: TlsErrorKind) -> TlsResult<()> {
+        let errno = io::Error::last_os_error().raw_os_error();
+        let io = self.callback_error();
         if rv == 0 {
             Ok(())
         } else {
-            Err(TlsError {
-                msg: self.error(),
-                code: rv,
-            })
+            Err(self.last_error(errno, io, default))
         }
     }
 
@@ -232,7 +812,7 @@ impl TlsContext {
             let servername_c = str_c_ptr(servername);
             ffi::tls_connect_servername(self.ptr, hostname_c, port_c, servername_c)
         };
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
     }
 
     #[cfg(unix)]
@@ -242,7 +822,7 @@ impl TlsContext {
             let servername_c = str_c_ptr(servername);
             ffi::tls_connect_socket(self.ptr, fd, servername_c)
         };
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
     }
 
     #[cfg(windows)]
@@ -254,7 +834,30 @@ impl TlsContext {
             // http://stackoverflow.com/questions/1953639/
             ffi::tls_connect_socket(self.ptr, sock as i32, servername_c)
         };
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
+    }
+
+    /// Establish a TLS connection over a Rust stream, libtls will do all I/O
+    /// through callbacks into `stream`. The context keeps the stream alive.
+    pub fn connect_cbs<S: Read + Write + 'static>(&mut self,
+                                                   stream: &CallbackStream<S>,
+                                                   servername: &str)
+                                                   -> TlsResult<()> {
+        let rv = unsafe {
+            // Keep the name alive for the call, NULL if there is no name
+            let servername_c = if servername.is_empty() {
+                None
+            } else {
+                Some(CString::from_vec_unchecked(servername.bytes().collect()))
+            };
+            ffi::tls_connect_cbs(self.ptr,
+                                 read_cb::<S>,
+                                 write_cb::<S>,
+                                 stream.cb_arg(),
+                                 servername_c.as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+        };
+        self.cbs = Some(stream.state.clone());
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
     }
 
     pub fn conn_version(&self) -> String {
@@ -263,15 +866,62 @@ impl TlsContext {
     pub fn conn_cipher(&self) -> String {
         unsafe { from_cstr(ffi::tls_conn_cipher(self.ptr)) }
     }
+    /// True if the session was resumed from a previous connection
+    pub fn conn_session_resumed(&self) -> bool {
+        let rv = unsafe { ffi::tls_conn_session_resumed(self.ptr) };
+        rv == 1
+    }
+    /// The server name requested by the client via SNI (server-side), None if
+    /// the client did not send one
+    pub fn conn_servername(&self) -> Option<String> {
+        let p = unsafe { ffi::tls_conn_servername(self.ptr) };
+        if p.is_null() {
+            None
+        } else {
+            Some(from_cstr(p))
+        }
+    }
+    /// The ALPN protocol selected in the handshake, None if no protocol
+    /// was selected
+    pub fn conn_alpn_selected(&self) -> Option<String> {
+        let p = unsafe { ffi::tls_conn_alpn_selected(self.ptr) };
+        if p.is_null() {
+            None
+        } else {
+            Some(from_cstr(p))
+        }
+    }
 
     /// Apply configuration settings to the context, consuming the config struct
     ///
     /// This should be called BEFORE trying to establish/accept
     /// a connection
     pub fn configure(&mut self, cfg: TlsConfig) -> TlsResult<()> {
+        self.configure_shared(Arc::new(cfg))
+    }
+
+    /// Apply a configuration shared with other contexts, libtls keeps a reference
+    /// to it for as long as the context lives
+    pub fn configure_shared(&mut self, cfg: Arc<TlsConfig>) -> TlsResult<()> {
         let rv = unsafe { ffi::tls_configure(self.ptr, cfg.cfg) };
         self.cfg = Some(cfg);
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Config)
+    }
+
+    /// Keep `server`, the context this connection was accepted from, alive for as
+    /// long as the connection. libtls reads the server certificates (SNI) from it
+    /// during the handshake.
+    pub fn set_server(&mut self, server: Arc<ServerContext>) {
+        self.server = Some(server);
+    }
+
+    /// The configuration applied with `configure()`, None if there is none or
+    /// if it is shared with other contexts
+    pub fn config_mut(&mut self) -> Option<&mut TlsConfig> {
+        match self.cfg {
+            Some(ref mut cfg) => Arc::get_mut(cfg),
+            None => None,
+        }
     }
 
     pub fn peer_cert_notbefore(&self) -> TlsResult<DateTime<UTC>> {
@@ -309,34 +959,65 @@ impl TlsContext {
             let name_c = CString::from_vec_unchecked(name.bytes().collect());
             ffi::tls_peer_cert_contains_name(self.ptr, name_c.as_ptr())
         };
-        (rv == 1)
+        rv == 1
     }
 
     pub fn peer_cert_provided(&self) -> bool {
         let rv = unsafe { ffi::tls_peer_cert_provided(self.ptr) };
-        (rv == 1)
+        rv == 1
     }
 
-    fn rv_to_result_io(&self, rv: i64) -> TlsResult<usize> {
+    /// The certificate chain presented by the peer in PEM format, None if
+    /// the peer did not present a certificate
+    pub fn peer_cert_chain_pem(&self) -> Option<Vec<u8>> {
+        let mut len = 0;
+        let p = unsafe { ffi::tls_peer_cert_chain_pem(self.ptr, &mut len) };
+        if p.is_null() {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(p, len) }.to_vec())
+        }
+    }
+
+    /// The OCSP response status (`ffi::OCSP_RESPONSE_*`), -1 if there is no response
+    pub fn peer_ocsp_response_status(&self) -> i32 {
+        unsafe { ffi::tls_peer_ocsp_response_status(self.ptr) }
+    }
+    /// The certificate status (`ffi::OCSP_CERT_*`), -1 if there is no response
+    pub fn peer_ocsp_cert_status(&self) -> i32 {
+        unsafe { ffi::tls_peer_ocsp_cert_status(self.ptr) }
+    }
+    /// The revocation reason (`ffi::CRL_REASON_*`), -1 if there is no response
+    pub fn peer_ocsp_crl_reason(&self) -> i32 {
+        unsafe { ffi::tls_peer_ocsp_crl_reason(self.ptr) }
+    }
+    /// Textual description of the OCSP result
+    pub fn peer_ocsp_result(&self) -> String {
+        unsafe { from_cstr(ffi::tls_peer_ocsp_result(self.ptr)) }
+    }
+    /// The OCSP responder URL in the peer certificate
+    pub fn peer_ocsp_url(&self) -> String {
+        unsafe { from_cstr(ffi::tls_peer_ocsp_url(self.ptr)) }
+    }
+    pub fn peer_ocsp_revocation_time(&self) -> Option<DateTime<UTC>> {
+        time_to_datetime(unsafe { ffi::tls_peer_ocsp_revocation_time(self.ptr) })
+    }
+    pub fn peer_ocsp_this_update(&self) -> Option<DateTime<UTC>> {
+        time_to_datetime(unsafe { ffi::tls_peer_ocsp_this_update(self.ptr) })
+    }
+    pub fn peer_ocsp_next_update(&self) -> Option<DateTime<UTC>> {
+        time_to_datetime(unsafe { ffi::tls_peer_ocsp_next_update(self.ptr) })
+    }
+
+    fn rv_to_result_io(&self, rv: i64, default: TlsErrorKind) -> TlsResult<usize> {
+        let errno = io::Error::last_os_error().raw_os_error();
+        let io = self.callback_error();
         match rv {
-            ffi::WANT_POLLIN => {
-                Err(TlsError {
-                    msg: String::new(),
-                    code: rv,
-                })
-            }
+            ffi::WANT_POLLIN => Err(TlsError::with_kind(TlsErrorKind::WantPollIn, "TLS_WANT_POLLIN")),
             ffi::WANT_POLLOUT => {
-                Err(TlsError {
-                    msg: String::new(),
-                    code: rv,
-                })
-            }
-            rv if rv < 0 => {
-                Err(TlsError {
-                    msg: self.error(),
-                    code: -1,
-                })
+                Err(TlsError::with_kind(TlsErrorKind::WantPollOut, "TLS_WANT_POLLOUT"))
             }
+            rv if rv < 0 => Err(self.last_error(errno, io, default)),
             rv => Ok(rv as usize),
         }
     }
@@ -347,26 +1028,75 @@ impl TlsContext {
     /// can be called to complete the handshake.
     pub fn handshake(&mut self) -> TlsResult<()> {
         let rv = unsafe { ffi::tls_handshake(self.ptr) };
-        self.rv_to_result_io(rv as i64).map(|_| ())
+        try!(self.rv_to_result_io(rv as i64, TlsErrorKind::Handshake));
+        self.verify_pins()
+    }
+
+    /// Only accept peers whose certificate hash (see `peer_cert_hash()`) is one of
+    /// `hashes`. The check runs once the handshake completes, `read()` and `write()`
+    /// complete the handshake first and fail if the peer is not pinned.
+    pub fn set_pins(&mut self, hashes: &[&str]) {
+        self.pins = hashes.iter().map(|h| h.to_lowercase()).collect();
+        self.pin_check = None;
+    }
+
+    fn pin_error(&self) -> TlsError {
+        TlsError::with_kind(TlsErrorKind::PinMismatch,
+                            format!("peer certificate hash {} is not pinned",
+                                    self.peer_cert_hash()))
+    }
+
+    /// Check the peer certificate against the pinned hashes
+    fn verify_pins(&mut self) -> TlsResult<()> {
+        if self.pins.is_empty() {
+            return Ok(());
+        }
+        let hash = self.peer_cert_hash().to_lowercase();
+        let ok = !hash.is_empty() && self.pins.contains(&hash);
+        self.pin_check = Some(ok);
+        if ok {
+            Ok(())
+        } else {
+            Err(self.pin_error())
+        }
+    }
+
+    /// With pinned hashes, make sure the handshake is complete and the peer
+    /// is pinned before any application data is exchanged
+    fn check_pins(&mut self) -> TlsResult<()> {
+        if self.pins.is_empty() {
+            return Ok(());
+        }
+        match self.pin_check {
+            Some(true) => Ok(()),
+            Some(false) => Err(self.pin_error()),
+            None => self.handshake(),
+        }
     }
 
     pub fn close(&mut self) -> TlsResult<()> {
         let rv = unsafe { ffi::tls_close(self.ptr) };
-        self.rv_to_result_io(rv as i64).map(|_| ())
+        let res = self.rv_to_result_io(rv as i64, TlsErrorKind::Other).map(|_| ());
+        if res.is_ok() {
+            self.closed = true;
+        }
+        res
     }
 
     pub fn read(&mut self, buf: &mut [u8]) -> TlsResult<usize> {
+        try!(self.check_pins());
         let buflen = buf.len() as size_t;
         let bptr = buf.as_mut_ptr() as *mut c_void;
         let rv = unsafe { ffi::tls_read(self.ptr, bptr, buflen) };
-        self.rv_to_result_io(rv as i64)
+        self.rv_to_result_io(rv as i64, TlsErrorKind::Other)
     }
 
     pub fn write(&mut self, buf: &[u8]) -> TlsResult<usize> {
+        try!(self.check_pins());
         let buflen = buf.len() as size_t;
         let bptr = buf.as_ptr() as *const c_void;
         let rv = unsafe { ffi::tls_write(self.ptr, bptr, buflen) };
-        self.rv_to_result_io(rv as i64)
+        self.rv_to_result_io(rv as i64, TlsErrorKind::Other)
     }
 
     /// Create new server context
@@ -378,6 +1108,11 @@ impl TlsContext {
             Ok(TlsContext {
                 ptr: p,
                 cfg: None,
+                cbs: None,
+                closed: false,
+                pins: Vec::new(),
+                pin_check: None,
+                server: None,
             })
         }
     }
@@ -387,11 +1122,17 @@ impl TlsContext {
     pub fn accept_socket(&mut self, fd: RawFd) -> TlsResult<TlsContext> {
         let mut cctx: ffi::Tls = ptr::null_mut();;
         let rv = unsafe { ffi::tls_accept_socket(self.ptr, &mut cctx, fd) };
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
             .map(|_| {
                 TlsContext {
                     ptr: cctx,
-                    cfg: None,
+                    // libtls connections keep a reference to the server config
+                    cfg: self.cfg.clone(),
+                    cbs: None,
+                    closed: false,
+                    pins: Vec::new(),
+                    pin_check: None,
+                    server: None,
                 }
             })
     }
@@ -403,16 +1144,69 @@ impl TlsContext {
         // This cast is not exactly safe
         // http://stackoverflow.com/questions/1953639/
         let rv = unsafe { ffi::tls_accept_socket(self.ptr, &mut cctx, sock as i32) };
-        self.rv_to_result(rv as i64)
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
             .map(|_| {
                 TlsContext {
                     ptr: cctx,
-                    cfg: None,
+                    // libtls connections keep a reference to the server config
+                    cfg: self.cfg.clone(),
+                    cbs: None,
+                    closed: false,
+                    pins: Vec::new(),
+                    pin_check: None,
+                    server: None,
+                }
+            })
+    }
+
+    /// Accept a new TLS connection over a Rust stream, see `connect_cbs()`
+    pub fn accept_cbs<S: Read + Write + 'static>(&mut self,
+                                                  stream: &CallbackStream<S>)
+                                                  -> TlsResult<TlsContext> {
+        let mut cctx: ffi::Tls = ptr::null_mut();
+        let rv = unsafe {
+            ffi::tls_accept_cbs(self.ptr,
+                                &mut cctx,
+                                read_cb::<S>,
+                                write_cb::<S>,
+                                stream.cb_arg())
+        };
+        self.rv_to_result(rv as i64, TlsErrorKind::Other)
+            .map(|_| {
+                TlsContext {
+                    ptr: cctx,
+                    // libtls connections keep a reference to the server config
+                    cfg: self.cfg.clone(),
+                    cbs: Some(stream.state.clone()),
+                    closed: false,
+                    pins: Vec::new(),
+                    pin_check: None,
+                    server: None,
                 }
             })
     }
 }
 
+/// A server context shared by a `TlsServer` and the connections accepted from it
+pub struct ServerContext(Mutex<TlsContext>);
+
+// libtls contexts are not thread safe. A server context is only used with its
+// lock held, and is never reconfigured once shared: connections read it during
+// their handshakes (SNI) and keep it alive, servers swap in a new context instead.
+unsafe impl Send for ServerContext {}
+unsafe impl Sync for ServerContext {}
+
+impl ServerContext {
+    pub fn new(ctx: TlsContext) -> ServerContext {
+        ServerContext(Mutex::new(ctx))
+    }
+
+    /// Lock the context, a panic while holding the lock leaves it usable
+    pub fn lock(&self) -> MutexGuard<'_, TlsContext> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
 impl Drop for TlsContext {
     fn drop(&mut self) {
         unsafe {
@@ -421,6 +1215,15 @@ impl Drop for TlsContext {
     }
 }
 
+/// Convert a libtls timestamp, where -1 means unavailable
+fn time_to_datetime(t: libc::time_t) -> Option<DateTime<UTC>> {
+    if t == -1 {
+        None
+    } else {
+        Some(DateTime::from_utc(NaiveDateTime::from_timestamp(t as i64, 0), UTC))
+    }
+}
+
 /// Initialize libtls - make sure to call this before using the API
 /// Returns false if libtls failed to initialise.
 pub fn init() -> bool {
@@ -433,6 +1236,27 @@ pub fn init() -> bool {
     unsafe { (RET == 0) }
 }
 
+#[test]
+fn classify_errors() {
+    let errno = Some(9);
+    let ebadf = format!("handshake failed: {}", unsafe { from_cstr(libc::strerror(9)) });
+    assert_eq!(classify(&ebadf, errno, TlsErrorKind::Other), TlsErrorKind::Io(Some(9)));
+    // The errno is stale, libtls did not report it
+    assert_eq!(classify("handshake failed: unexpected message", errno, TlsErrorKind::Other),
+               TlsErrorKind::Handshake);
+    assert_eq!(classify("name `example.com' not present in server certificate", None, TlsErrorKind::Other),
+               TlsErrorKind::NameMismatch);
+    assert_eq!(classify("certificate verification failed: unable to get local issuer certificate",
+                        None,
+                        TlsErrorKind::Handshake),
+               TlsErrorKind::Verify);
+    assert_eq!(classify("certificate verification failed: certificate revoked",
+                        None,
+                        TlsErrorKind::Handshake),
+               TlsErrorKind::Revoked);
+    assert_eq!(classify("", None, TlsErrorKind::Config), TlsErrorKind::Config);
+}
+
 #[test]
 fn connect_servername() {
     assert!(init());
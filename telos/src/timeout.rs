@@ -0,0 +1,96 @@
+//! Timeouts for blocking streams. libtls has no timeouts of its own, so the
+//! socket is made non-blocking for the duration of the operation and poll()
+//! waits for the readiness libtls asks for.
+
+extern crate libc;
+
+use std::cmp;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+use raw::{Interest, TlsError, TlsErrorKind, TlsResult};
+
+/// Restores the blocking mode of a socket when dropped
+struct NonBlocking {
+    fd: RawFd,
+    flags: libc::c_int,
+}
+
+impl NonBlocking {
+    fn set(fd: RawFd) -> io::Result<NonBlocking> {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & libc::O_NONBLOCK == 0 &&
+           unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(NonBlocking {
+            fd: fd,
+            flags: flags,
+        })
+    }
+}
+
+impl Drop for NonBlocking {
+    fn drop(&mut self) {
+        if self.flags & libc::O_NONBLOCK == 0 {
+            unsafe { libc::fcntl(self.fd, libc::F_SETFL, self.flags) };
+        }
+    }
+}
+
+/// Wait until `fd` has the given readiness, false if `timeout` expired first
+pub fn poll(fd: RawFd, interest: Interest, timeout: Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd: fd,
+        events: match interest {
+            Interest::Read => libc::POLLIN,
+            Interest::Write => libc::POLLOUT,
+        },
+        revents: 0,
+    };
+    // Round up, so we never wake up just before the deadline
+    let ms = timeout.as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(timeout.subsec_nanos().div_ceil(1_000_000)));
+    let ms = cmp::min(ms, libc::c_int::MAX as u64) as libc::c_int;
+    match unsafe { libc::poll(&mut pfd, 1, ms) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+/// Repeat the non-blocking operation `op` over socket `fd` until it completes,
+/// fails with a `TlsErrorKind::Timeout` error once `timeout` has passed
+pub fn with_timeout<R, F>(fd: RawFd, timeout: Duration, mut op: F) -> TlsResult<R>
+    where F: FnMut() -> TlsResult<R>
+{
+    let deadline = Instant::now() + timeout;
+    let _nonblocking = try!(NonBlocking::set(fd));
+    loop {
+        let interest = match op() {
+            Err(err) => {
+                match err.interest() {
+                    Some(interest) => interest,
+                    None => return Err(err),
+                }
+            }
+            res => return res,
+        };
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(TlsError::with_kind(TlsErrorKind::Timeout, "operation timed out"));
+            }
+            match poll(fd, interest, deadline - now) {
+                Ok(true) => break,
+                Ok(false) => continue,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(TlsError::from(err)),
+            }
+        }
+    }
+}
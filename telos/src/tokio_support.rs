@@ -0,0 +1,210 @@
+//! Tokio integration, enabled with the `tokio` feature.
+//!
+//! `TlsConnector` and `TlsAcceptor` wrap `ClientConfig` and `TlsServer`, their
+//! futures resolve to an `AsyncTlsStream` once the handshake is complete.
+//! libtls does its I/O directly on the socket, the tokio readiness of the
+//! socket is used to know when to retry.
+//!
+//! ```no_run
+//! # extern crate telos;
+//! # extern crate tokio;
+//! use tokio::io::AsyncWriteExt;
+//! use tokio::net::TcpStream;
+//! use telos::tokio_support::TlsConnector;
+//!
+//! # fn main() {
+//! let rt = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+//! let tcp = rt.block_on(TcpStream::connect("google.com:443")).unwrap();
+//! let connector = TlsConnector::new(telos::new_client().build().unwrap());
+//! let mut stream = rt.block_on(connector.connect(tcp, "google.com")).unwrap();
+//! rt.block_on(stream.write_all(b"GET / HTTP/1.1\n\n")).unwrap();
+//! # }
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use super::{ClientConfig, Interest, Status, TlsResult, TlsServer, TlsStream};
+use raw::TlsContext;
+
+/// Async TLS client, see `connect()`. Cloning it shares the configuration.
+#[derive(Clone)]
+pub struct TlsConnector {
+    config: ClientConfig,
+}
+
+impl TlsConnector {
+    pub fn new(config: ClientConfig) -> TlsConnector {
+        TlsConnector { config: config }
+    }
+
+    /// Start a TLS connection over `tcp`, the returned future completes
+    /// once the handshake is done
+    pub fn connect(&self, tcp: TcpStream, servername: &str) -> Handshake {
+        let res = self.config
+            .connect(tcp, servername)
+            .map(AsyncTlsStream::new)
+            .map_err(io::Error::from);
+        Handshake { state: Some(res) }
+    }
+}
+
+impl From<ClientConfig> for TlsConnector {
+    fn from(config: ClientConfig) -> TlsConnector {
+        TlsConnector::new(config)
+    }
+}
+
+/// Async TLS server, see `accept()`
+pub struct TlsAcceptor {
+    server: TlsServer,
+}
+
+impl TlsAcceptor {
+    pub fn new(server: TlsServer) -> TlsAcceptor {
+        TlsAcceptor { server: server }
+    }
+
+    /// Start a TLS connection over an accepted `tcp` connection, the returned
+    /// future completes once the handshake is done
+    pub fn accept(&self, tcp: TcpStream) -> Handshake {
+        let res = self.server.accept(tcp).map(AsyncTlsStream::new);
+        Handshake { state: Some(res) }
+    }
+}
+
+impl From<TlsServer> for TlsAcceptor {
+    fn from(server: TlsServer) -> TlsAcceptor {
+        TlsAcceptor::new(server)
+    }
+}
+
+/// Future driving the TLS handshake, see `TlsConnector::connect()` and
+/// `TlsAcceptor::accept()`
+pub struct Handshake {
+    state: Option<io::Result<AsyncTlsStream>>,
+}
+
+impl Future for Handshake {
+    type Output = io::Result<AsyncTlsStream>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut stream = match this.state.take() {
+            Some(Ok(stream)) => stream,
+            Some(Err(err)) => return Poll::Ready(Err(err)),
+            None => panic!("Handshake polled after completion"),
+        };
+        match stream.poll_op(cx, Interest::Write, |ctx| ctx.handshake()) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(stream)),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => {
+                this.state = Some(Ok(stream));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A TLS stream over a tokio `TcpStream`, implements `AsyncRead` and `AsyncWrite`
+pub struct AsyncTlsStream {
+    stream: TlsStream<TcpStream>,
+}
+
+impl AsyncTlsStream {
+    fn new(stream: TlsStream<TcpStream>) -> AsyncTlsStream {
+        AsyncTlsStream { stream: stream }
+    }
+
+    /// The underlying `TlsStream`, e.g. to check certificate information
+    pub fn get_ref(&self) -> &TlsStream<TcpStream> {
+        &self.stream
+    }
+
+    /// Run a libtls operation until it completes. libtls does its own I/O on the
+    /// socket, so the operation is retried inside `try_io()` to let tokio clear
+    /// the readiness once libtls blocks on it.
+    fn poll_op<R, F>(&mut self, cx: &mut Context, first: Interest, mut op: F) -> Poll<io::Result<R>>
+        where F: FnMut(&mut TlsContext) -> TlsResult<R>
+    {
+        let TlsStream { ref mut ctx, ref inner_stream, .. } = self.stream;
+        let mut interest = first;
+        loop {
+            let ready = match interest {
+                Interest::Read => inner_stream.poll_read_ready(cx),
+                Interest::Write => inner_stream.poll_write_ready(cx),
+            };
+            match ready {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let mut done = None;
+            let res = inner_stream.try_io(tokio_interest(interest), || {
+                match Status::from_result(op(ctx)) {
+                    Ok(Status::Ready(val)) => done = Some(Ok(val)),
+                    Ok(Status::Blocked(blocked)) if blocked == interest => {
+                        return Err(io::Error::new(io::ErrorKind::WouldBlock, "TLS would block"));
+                    }
+                    Ok(Status::Blocked(blocked)) => interest = blocked,
+                    Err(err) => done = Some(Err(io::Error::from(err))),
+                }
+                Ok(())
+            });
+            match res {
+                Ok(()) => {
+                    if let Some(res) = done {
+                        return Poll::Ready(res);
+                    }
+                }
+                // The readiness was cleared, poll it again
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => (),
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+fn tokio_interest(interest: Interest) -> tokio::io::Interest {
+    match interest {
+        Interest::Read => tokio::io::Interest::READABLE,
+        Interest::Write => tokio::io::Interest::WRITABLE,
+    }
+}
+
+impl AsyncRead for AsyncTlsStream {
+    fn poll_read(self: Pin<&mut Self>,
+                 cx: &mut Context,
+                 buf: &mut ReadBuf)
+                 -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_op(cx, Interest::Read, |ctx| ctx.read(buf.initialize_unfilled())) {
+            Poll::Ready(Ok(len)) => {
+                buf.advance(len);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncWrite for AsyncTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_op(cx, Interest::Write, |ctx| ctx.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Close the TLS connection, the TCP connection is left open
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().poll_op(cx, Interest::Write, |ctx| ctx.close())
+    }
+}
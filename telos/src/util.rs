@@ -6,6 +6,7 @@ extern crate ws2_32;
 use std::mem;
 use std::ffi::{CStr, CString};
 use libc::c_char;
+use std::ops::Deref;
 use std::ptr;
 
 #[cfg(windows)]
@@ -41,3 +42,27 @@ pub fn str_c_ptr(s: &str) -> *const i8 {
         unsafe { CString::from_vec_unchecked(s.bytes().collect()).as_ptr() }
     }
 }
+
+/// Bytes that are zeroed when dropped, for private keys kept in memory
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    pub fn new(bytes: &[u8]) -> Secret {
+        Secret(bytes.to_vec())
+    }
+}
+
+impl Deref for Secret {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        for b in self.0.iter_mut() {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Loopback fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use telos::{TlsResult, TlsServer, TlsStream};
+
+/// Run `client` against `tls_srv` over a loopback connection. `client` runs on its
+/// own thread with the server address, `server` runs on the accepted connection.
+/// Returns the server and client results.
+pub fn loopback<C, R, S, T>(tls_srv: &TlsServer, client: C, server: S) -> (T, R)
+    where C: FnOnce(SocketAddr) -> R + Send + 'static,
+          R: Send + 'static,
+          S: FnOnce(TlsStream<TcpStream>) -> T
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let cli = thread::spawn(move || client(addr));
+
+    let (tcp_conn, _) = listener.accept().unwrap();
+    let srv_res = server(tls_srv.accept(tcp_conn).unwrap());
+
+    (srv_res, cli.join().unwrap())
+}
+
+/// Handshake between `tls_srv` and a client running `client` over a loopback
+/// connection, returns the server handshake result and the client result
+pub fn handshake_with<F, R>(tls_srv: TlsServer, client: F) -> (TlsResult<()>, R)
+    where F: FnOnce(TcpStream) -> R + Send + 'static,
+          R: Send + 'static
+{
+    loopback(&tls_srv,
+             |addr| client(TcpStream::connect(addr).unwrap()),
+             |mut tls_conn| tls_conn.handshake())
+}
@@ -1,6 +1,10 @@
 extern crate telos;
+mod common;
+
+use std::io;
 use std::io::{Read, Write};
-use telos::new_client;
+use telos::{new_client, TlsBuilder, TlsStream};
+use common::loopback;
 use std::net::{TcpStream, TcpListener};
 use std::thread;
 use std::time::Duration;
@@ -224,3 +228,88 @@ fn inner_stream() {
         client.inner().shutdown(Shutdown::Both).unwrap();
         assert!(client.handshake().is_err());
 }
+
+#[test]
+fn ca_file_error_message() {
+    let err = new_client()
+                .ca_file("tests/missing.pem")
+                .connect_stream(io::empty(), "localhost")
+                .err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    // The setting, the path and the libtls reason
+    let msg = err.to_string();
+    assert!(msg.contains("CA file"));
+    assert!(msg.contains("tests/missing.pem"));
+    assert!(msg.contains("No such file"));
+}
+
+#[test]
+fn ciphers_error_message() {
+    let err = new_client()
+                .ciphers("unknown_cipher")
+                .connect_stream(io::empty(), "localhost")
+                .err().unwrap();
+    assert!(err.to_string().starts_with("Invalid ciphers unknown_cipher: "));
+}
+
+/// The TLS server for the `connect_to()` tests
+fn local_server() -> telos::TlsServer {
+    telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind()
+        .unwrap()
+}
+
+/// Greet the client once the handshake is done
+fn hello(mut conn: TlsStream<TcpStream>) {
+    let _ = conn.write(b"hello");
+}
+
+#[test]
+fn connect_to() {
+    loopback(&local_server(), |addr| {
+        // localhost may resolve to ::1 first, the server only listens on IPv4
+        let mut c = new_client()
+            .ca_file("tests/ca.crt")
+            .connect_to_addr(&format!("localhost:{}", addr.port())).unwrap();
+        let mut buf = [0u8; 5];
+        c.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        assert!(c.inner().peer_addr().unwrap().ip().is_loopback());
+    }, hello);
+}
+
+#[test]
+fn connect_to_servername() {
+    loopback(&local_server(), |addr| {
+        let mut c = new_client()
+            .ca_file("tests/ca.crt")
+            .servername("localhost")
+            .connect_to("127.0.0.1", addr.port()).unwrap();
+        c.handshake().unwrap();
+        assert!(c.peer_cert_contains_name("localhost"));
+    }, hello);
+
+    // The certificate is verified against the servername, not the address
+    loopback(&local_server(), |addr| {
+        let mut c = new_client()
+            .ca_file("tests/ca.crt")
+            .servername("example.com")
+            .connect_to("127.0.0.1", addr.port()).unwrap();
+        assert_eq!(c.handshake().unwrap_err().kind(), telos::TlsErrorKind::NameMismatch);
+    }, hello);
+}
+
+#[test]
+fn connect_to_errors() {
+    // Nothing listens on this port anymore
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let err = new_client().connect_to("127.0.0.1", port).err().unwrap();
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::ConnectionRefused);
+
+    let err = new_client().connect_to_addr("localhost:https").err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    let err = new_client().connect_to_addr("localhost").err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+}
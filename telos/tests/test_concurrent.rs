@@ -0,0 +1,108 @@
+extern crate telos;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use telos::TlsBuilder;
+
+const THREADS: usize = 8;
+const CONNECTIONS: usize = 16;
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn server_send_sync() {
+    assert_send::<telos::TlsServer>();
+    assert_sync::<telos::TlsServer>();
+    assert_send::<telos::TlsStream<TcpStream>>();
+    assert_send::<telos::TlsStream<telos::CallbackStream<TcpStream>>>();
+    assert_send::<telos::ClientConfig>();
+    assert_sync::<telos::ClientConfig>();
+}
+
+/// Stream accepted on one thread and moved to another
+#[test]
+fn stream_across_threads() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind()
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let cli = thread::spawn(move || {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.write_all(b"ping").unwrap();
+    });
+
+    let (tcp_conn, _) = listener.accept().unwrap();
+    let mut conn = tls_srv.accept(tcp_conn).unwrap();
+    conn.handshake().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }).join().unwrap();
+    cli.join().unwrap();
+}
+
+/// Many threads accepting from the same server and listener
+#[test]
+fn concurrent_accept() {
+    let tls_srv = Arc::new(telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind()
+        .unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let servers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let tls_srv = tls_srv.clone();
+            let listener = listener.try_clone().unwrap();
+            thread::spawn(move || {
+                for _ in 0..CONNECTIONS {
+                    let (tcp_conn, _) = listener.accept().unwrap();
+                    let mut conn = tls_srv.accept(tcp_conn).unwrap();
+                    conn.handshake().unwrap();
+                    let mut buf = [0u8; 2];
+                    conn.read_exact(&mut buf).unwrap();
+                    conn.write_all(&buf).unwrap();
+                    // The client may be gone already
+                    let _ = conn.shutdown();
+                }
+            })
+        })
+        .collect();
+
+    let client = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .build()
+        .unwrap();
+    let clients: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for i in 0..CONNECTIONS {
+                    let tcp_stream = TcpStream::connect(addr).unwrap();
+                    let mut tls_stream = client.connect(tcp_stream, "localhost").unwrap();
+                    let msg = [t as u8, i as u8];
+                    tls_stream.write_all(&msg).unwrap();
+                    let mut buf = [0u8; 2];
+                    tls_stream.read_exact(&mut buf).unwrap();
+                    assert_eq!(buf, msg);
+                }
+            })
+        })
+        .collect();
+
+    for handle in clients.into_iter().chain(servers) {
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+extern crate telos;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use telos::{ClientConfig, ServerConfig, TlsBuilder};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn config_send_sync() {
+    assert_send_sync::<ClientConfig>();
+    assert_send_sync::<ServerConfig>();
+}
+
+#[test]
+fn shared_configs() {
+    let srv_config = Arc::new(telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .build().unwrap());
+    let cli_config = Arc::new(telos::new_client()
+        .ca_file("tests/ca.crt")
+        .build().unwrap());
+
+    let srv = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = srv.local_addr().unwrap();
+
+    let clients: Vec<_> = (0..4u8).map(|i| {
+        let cli_config = cli_config.clone();
+        thread::spawn(move ||{
+            let tcp_stream = TcpStream::connect(addr).unwrap();
+            let mut tls_stream = cli_config.connect(tcp_stream, "localhost").unwrap();
+            tls_stream.write(&[i]).unwrap();
+        })
+    }).collect();
+
+    let mut seen = Vec::new();
+    for tcp_conn in srv.incoming().take(4) {
+        // A new server from the shared config for each connection
+        let tls_srv = srv_config.bind().unwrap();
+        let mut tls_conn = tls_srv.accept(tcp_conn.unwrap()).unwrap();
+        let mut buf = [0u8; 1];
+        tls_conn.read_exact(&mut buf).unwrap();
+        seen.push(buf[0]);
+    }
+    for cli in clients {
+        cli.join().unwrap();
+    }
+    seen.sort();
+    assert_eq!(seen, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn config_build_error() {
+    let err = telos::new_client().ca_file("tests/no-such.crt").build().err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    let err = telos::new_server().key_file("tests/no-such.key").build().err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+}
+
+#[cfg(unix)]
+#[test]
+fn config_session_file() {
+    // Connections sharing the config would share the session file
+    let path = std::env::temp_dir().join(format!("telos-config-session-{}", std::process::id()));
+    let err = telos::new_client()
+        .session_file(path.to_str().unwrap())
+        .build().err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shared_config_ticket_key() {
+    let config = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .build().unwrap();
+    let tls_srv = config.bind().unwrap();
+    // The server builds its own config with the key, the shared one is unchanged
+    tls_srv.add_ticket_key(1, &[1u8; 48]).unwrap();
+    config.bind().unwrap();
+}
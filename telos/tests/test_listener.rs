@@ -0,0 +1,89 @@
+extern crate telos;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use telos::{TlsBuilder, TlsListener};
+
+fn listener() -> TlsListener {
+    let server = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt");
+    TlsListener::bind(server, "127.0.0.1:0").unwrap()
+}
+
+#[test]
+fn listener_incoming() {
+    let mut listener = listener();
+    let addr = listener.local_addr().unwrap();
+    assert!(addr.port() != 0);
+
+    let cli = thread::spawn(move ||{
+        for i in 0..3u8 {
+            let tcp_stream = TcpStream::connect(addr).unwrap();
+            let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+            tls_stream.write(&[i]).unwrap();
+        }
+    });
+
+    for (i, conn) in listener.incoming().take(3).enumerate() {
+        let mut conn = conn.unwrap();
+        // The handshake is already done
+        assert!(!conn.version().is_empty());
+        let mut buf = [0u8; 1];
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0] as usize, i);
+    }
+    cli.join().unwrap();
+}
+
+#[test]
+fn listener_bad_connection() {
+    let mut listener = listener();
+    let addr = listener.local_addr().unwrap();
+
+    let cli = thread::spawn(move ||{
+        // Not TLS
+        let mut tcp_stream = TcpStream::connect(addr).unwrap();
+        tcp_stream.write(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+        drop(tcp_stream);
+
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.write(b"hello").unwrap();
+    });
+
+    let mut incoming = listener.incoming();
+    assert!(incoming.next().unwrap().is_err());
+    // The iterator keeps going after a failed connection
+    let mut conn = incoming.next().unwrap().unwrap();
+    let mut buf = [0u8; 5];
+    conn.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    cli.join().unwrap();
+}
+
+#[test]
+fn listener_handshake_timeout() {
+    let mut listener = listener().handshake_timeout(Duration::from_millis(100));
+    let addr = listener.local_addr().unwrap();
+
+    // A client that never starts the handshake does not hold up the listener
+    let idle = TcpStream::connect(addr).unwrap();
+    let start = Instant::now();
+    assert!(listener.accept().is_err());
+    assert!(start.elapsed() < Duration::from_secs(10));
+    drop(idle);
+}
+
+#[test]
+fn listener_bind_error() {
+    let server = telos::new_server()
+        .key_file("tests/no-such.key")
+        .cert_file("tests/server.crt");
+    assert!(TlsListener::bind(server, "127.0.0.1:0").is_err());
+}
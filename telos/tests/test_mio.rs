@@ -0,0 +1,51 @@
+#![cfg(feature = "mio")]
+extern crate telos;
+extern crate mio;
+
+use std::time::Duration;
+use mio::{Events, Poll, PollOpt, Ready, Token};
+use mio::net::{TcpListener, TcpStream};
+use telos::{Interest, Status, TlsBuilder};
+
+#[test]
+fn mio_register_interest() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tcp_cli = TcpStream::connect(&addr).unwrap();
+
+    let poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.register(&listener, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+    poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+    let (tcp_srv, _) = listener.accept().unwrap();
+
+    let mut srv = tls_srv.accept(tcp_srv).unwrap();
+    assert_eq!(srv.try_handshake().unwrap(), Status::Blocked(Interest::Read));
+    assert_eq!(srv.interest(), Ready::readable());
+    poll.register(&srv, Token(1), srv.interest(), PollOpt::level()).unwrap();
+
+    // The client hello wakes up the server
+    let mut cli = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .connect(tcp_cli, "localhost").unwrap();
+    let mut woken = false;
+    for _ in 0..50 {
+        // Blocked on write until the non-blocking connect completes
+        match cli.try_handshake().unwrap() {
+            Status::Blocked(_) => (),
+            Status::Ready(()) => panic!("handshake completed without the server"),
+        }
+        poll.poll(&mut events, Some(Duration::from_millis(100))).unwrap();
+        if events.iter().any(|event| event.token() == Token(1) && event.readiness().is_readable()) {
+            woken = true;
+            break;
+        }
+    }
+    assert!(woken);
+    poll.deregister(&srv).unwrap();
+}
@@ -0,0 +1,101 @@
+extern crate telos;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use telos::{Interest, Status, TlsBuilder, TlsStream};
+
+/// Connected pair of non-blocking TLS streams over loopback
+fn nonblocking_pair() -> (TlsStream<TcpStream>, TlsStream<TcpStream>) {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    let srv = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = srv.local_addr().unwrap();
+
+    let tcp_cli = TcpStream::connect(addr).unwrap();
+    let tcp_srv = srv.incoming().next().unwrap().unwrap();
+    tcp_cli.set_nonblocking(true).unwrap();
+    tcp_srv.set_nonblocking(true).unwrap();
+
+    let cli = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .connect(tcp_cli, "localhost").unwrap();
+    let srv = tls_srv.accept(tcp_srv).unwrap();
+    (cli, srv)
+}
+
+/// Call f until it is ready, collecting the interests it blocked on
+fn until_ready<T, F>(mut f: F) -> (T, Vec<Interest>)
+    where F: FnMut() -> Status<T>
+{
+    let mut blocked = Vec::new();
+    for _ in 0..1000 {
+        match f() {
+            Status::Ready(val) => return (val, blocked),
+            Status::Blocked(interest) => blocked.push(interest),
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("operation never completed");
+}
+
+#[test]
+fn nonblocking_handshake() {
+    let (mut cli, mut srv) = nonblocking_pair();
+
+    // The server is waiting for the client hello
+    assert_eq!(srv.try_handshake().unwrap(), Status::Blocked(Interest::Read));
+
+    let mut cli_done = false;
+    let mut srv_done = false;
+    for _ in 0..1000 {
+        if !cli_done {
+            cli_done = cli.try_handshake().unwrap() == Status::Ready(());
+        }
+        if !srv_done {
+            srv_done = srv.try_handshake().unwrap() == Status::Ready(());
+        }
+        if cli_done && srv_done {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(cli_done && srv_done);
+    assert!(!cli.version().is_empty());
+}
+
+#[test]
+fn nonblocking_read_write() {
+    let (mut cli, mut srv) = nonblocking_pair();
+
+    // Nothing to read yet
+    let mut buf = [0u8; 128];
+    match srv.try_read(&mut buf).unwrap() {
+        Status::Blocked(Interest::Read) => (),
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    // Both ends need to progress to get through the handshake
+    let (len, _) = until_ready(|| {
+        let _ = srv.try_read(&mut buf).unwrap();
+        cli.try_write(b"hello").unwrap()
+    });
+    assert_eq!(len, 5);
+
+    let (len, _) = until_ready(|| srv.try_read(&mut buf).unwrap());
+    assert_eq!(&buf[..len], b"hello");
+
+    let (_, _) = until_ready(|| cli.try_shutdown().unwrap());
+}
+
+#[test]
+fn blocked_maps_to_would_block() {
+    use std::io::{ErrorKind, Read};
+
+    let (_cli, mut srv) = nonblocking_pair();
+    let mut buf = [0u8; 128];
+    let err = srv.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
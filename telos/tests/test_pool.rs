@@ -0,0 +1,212 @@
+extern crate telos;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use telos::{PoolServer, TlsBuilder, TlsStream};
+
+fn pool() -> PoolServer {
+    let server = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt");
+    PoolServer::bind(server, "127.0.0.1:0").unwrap()
+}
+
+fn connect(addr: std::net::SocketAddr) -> TlsStream<TcpStream> {
+    let tcp_stream = TcpStream::connect(addr).unwrap();
+    let mut tls_stream = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .connect(tcp_stream, "localhost").unwrap();
+    tls_stream.handshake().unwrap();
+    tls_stream
+}
+
+/// Echo everything until the peer closes
+fn echo(mut stream: TlsStream<TcpStream>) {
+    let mut buf = [0u8; 64];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if stream.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn pool_echo() {
+    let pool = pool().threads(4).spawn(echo).unwrap();
+    let addr = pool.local_addr();
+
+    let clients: Vec<_> = (0..8u8)
+        .map(|i| {
+            thread::spawn(move || {
+                let mut tls_stream = connect(addr);
+                tls_stream.write_all(&[i; 4]).unwrap();
+                let mut buf = [0u8; 4];
+                tls_stream.read_exact(&mut buf).unwrap();
+                assert_eq!(buf, [i; 4]);
+            })
+        })
+        .collect();
+    for cli in clients {
+        cli.join().unwrap();
+    }
+    pool.shutdown();
+}
+
+#[test]
+fn pool_max_connections() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let pool = pool()
+        .threads(2)
+        .max_connections(2)
+        .spawn(move |stream| {
+            tx.lock().unwrap().send(()).unwrap();
+            echo(stream);
+        })
+        .unwrap();
+    let addr = pool.local_addr();
+
+    let first = connect(addr);
+    let second = connect(addr);
+    rx.recv().unwrap();
+    rx.recv().unwrap();
+    assert_eq!(pool.connections(), 2);
+
+    // The TCP connection is queued by the kernel but not accepted
+    let third = thread::spawn(move || connect(addr));
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+    drop(first);
+    rx.recv().unwrap();
+    let third = third.join().unwrap();
+    assert_eq!(pool.connections(), 2);
+
+    drop(second);
+    drop(third);
+    pool.shutdown();
+}
+
+#[test]
+fn pool_handshake_timeout() {
+    let called = Arc::new(Mutex::new(false));
+    let handler_called = called.clone();
+    let pool = pool()
+        .handshake_timeout(Duration::from_millis(200))
+        .spawn(move |_| *handler_called.lock().unwrap() = true)
+        .unwrap();
+
+    // Connect but never start the handshake
+    let start = Instant::now();
+    let mut tcp_stream = TcpStream::connect(pool.local_addr()).unwrap();
+    tcp_stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(tcp_stream.read(&mut buf).unwrap(), 0);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(!*called.lock().unwrap());
+    pool.shutdown();
+}
+
+#[test]
+fn pool_bad_connection() {
+    let pool = pool().threads(1).spawn(echo).unwrap();
+    let addr = pool.local_addr();
+
+    let mut tcp_stream = TcpStream::connect(addr).unwrap();
+    tcp_stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    drop(tcp_stream);
+
+    // The worker is still available
+    let mut tls_stream = connect(addr);
+    tls_stream.write_all(b"ping").unwrap();
+    let mut buf = [0u8; 4];
+    tls_stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    pool.shutdown();
+}
+
+#[test]
+fn pool_handler_panic() {
+    let pool = pool()
+        .threads(1)
+        .spawn(|mut stream| {
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).unwrap();
+            if &buf == b"boom" {
+                panic!("handler panic");
+            }
+            stream.write_all(&buf).unwrap();
+        })
+        .unwrap();
+    let addr = pool.local_addr();
+
+    let mut tls_stream = connect(addr);
+    tls_stream.write_all(b"boom").unwrap();
+    let mut buf = [0u8; 4];
+    assert!(tls_stream.read_exact(&mut buf).is_err());
+
+    // The only worker is still running
+    let mut tls_stream = connect(addr);
+    tls_stream.write_all(b"ping").unwrap();
+    tls_stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    pool.shutdown();
+}
+
+#[test]
+fn pool_graceful_shutdown() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let pool = pool()
+        .spawn(move |stream| {
+            tx.lock().unwrap().send(()).unwrap();
+            echo(stream);
+        })
+        .unwrap();
+    let addr = pool.local_addr();
+
+    let mut tls_stream = connect(addr);
+    rx.recv().unwrap();
+
+    pool.shutdown();
+    // The server closed the connection with close_notify
+    let mut buf = [0u8; 1];
+    assert_eq!(tls_stream.read(&mut buf).unwrap(), 0);
+
+    // No longer accepting
+    assert!(TcpStream::connect(addr)
+        .and_then(|mut tcp| tcp.read(&mut buf))
+        .map(|n| n == 0)
+        .unwrap_or(true));
+}
+
+#[test]
+fn pool_shutdown_timeout() {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let pool = pool()
+        .spawn(move |mut stream| {
+            tx.lock().unwrap().send(()).unwrap();
+            // The client never reads, this blocks once the socket buffers are full
+            while stream.write_all(&[0u8; 1024]).is_ok() {}
+        })
+        .unwrap();
+
+    let tls_stream = connect(pool.local_addr());
+    rx.recv().unwrap();
+
+    // The handler does not notice the shutdown, its connection is closed
+    let start = Instant::now();
+    pool.shutdown_timeout(Duration::from_millis(200));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_secs(10));
+    drop(tls_stream);
+}
@@ -1,13 +1,19 @@
 
 extern crate telos;
+mod common;
+
+use std::io;
 use std::io::{Write,Read};
 use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
+use telos::TlsBuilder;
+use common::{handshake_with, loopback};
 
 #[test]
 fn tls_server() {
-    let mut tls_srv = telos::new_server()
+    let tls_srv = telos::new_server()
         .key_file("tests/private_key.key")
         .cert_file("tests/certificate.crt")
         .bind().unwrap();
@@ -36,7 +42,7 @@ fn tls_server() {
 
 #[test]
 fn double_handshake() {
-    let mut tls_srv = telos::new_server()
+    let tls_srv = telos::new_server()
         .key_file("tests/private_key.key")
         .cert_file("tests/certificate.crt")
         .bind().unwrap();
@@ -72,7 +78,7 @@ fn double_handshake() {
 
 #[test]
 fn server_handshake_does_nothing() {
-    let mut tls_srv = telos::new_server()
+    let tls_srv = telos::new_server()
         .key_file("tests/private_key.key")
         .cert_file("tests/certificate.crt")
         .bind().unwrap();
@@ -94,3 +100,888 @@ fn server_handshake_does_nothing() {
     tls_conn.handshake().unwrap();
     let _ = cli.join();
 }
+
+#[test]
+fn client_certificate() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ca_file("tests/ca.crt")
+        .verify_client()
+        .bind().unwrap();
+
+    loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .cert_file("tests/client.crt")
+                .key_file("tests/client.key")
+                .connect(tcp_stream, "localhost").unwrap();
+        let mut buf = [0u8; 128];
+        let len = tls_stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        assert!(tls_conn.peer_cert_provided());
+        assert!(tls_conn.certificate_subject().contains("telos client"));
+        assert!(tls_conn.certificate_issuer().contains("telos test CA"));
+        assert!(!tls_conn.certificate_hash().is_empty());
+        tls_conn.write(b"hello").unwrap();
+    });
+}
+
+#[test]
+fn client_certificate_missing() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ca_file("tests/ca.crt")
+        .verify_client()
+        .bind().unwrap();
+
+    let (srv_res, _) = handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        let _ = tls_stream.handshake();
+    });
+    assert!(srv_res.is_err());
+}
+
+#[test]
+fn client_certificate_optional() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ca_file("tests/ca.crt")
+        .verify_client_optional()
+        .bind().unwrap();
+
+    loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        let mut buf = [0u8; 128];
+        let len = tls_stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        assert!(!tls_conn.peer_cert_provided());
+        tls_conn.write(b"hello").unwrap();
+    });
+}
+
+fn key_mem_roundtrip(tls_srv: &telos::TlsServer) {
+    loopback(tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        let mut buf = [0u8; 128];
+        let len = tls_stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }, |mut tls_conn| {
+        tls_conn.write(b"hello").unwrap();
+    });
+}
+
+#[test]
+fn key_cert_mem() {
+    let tls_srv = telos::new_server()
+        .key(include_bytes!("server.key"))
+        .cert(include_bytes!("server.crt"))
+        .bind().unwrap();
+    key_mem_roundtrip(&tls_srv);
+}
+
+#[test]
+fn keypair_mem() {
+    let tls_srv = telos::new_server()
+        .keypair(include_bytes!("server.crt"), include_bytes!("server.key"))
+        .bind().unwrap();
+    key_mem_roundtrip(&tls_srv);
+}
+
+#[test]
+fn key_mem_invalid() {
+    let tls_srv = telos::new_server()
+        .key(b"--INVALID PEM")
+        .cert(include_bytes!("server.crt"))
+        .bind();
+    assert!(tls_srv.is_err());
+}
+
+fn alpn_roundtrip(server_alpn: &[&str], client_alpn: &'static [&'static str]) -> Option<String> {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .alpn(server_alpn)
+        .bind().unwrap();
+
+    let (selected, cli_selected) = loopback(&tls_srv, move |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .alpn(client_alpn)
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        tls_stream.alpn_selected()
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        tls_conn.alpn_selected()
+    });
+
+    assert_eq!(cli_selected, selected);
+    selected
+}
+
+#[test]
+fn alpn() {
+    assert_eq!(alpn_roundtrip(&["h2", "http/1.1"], &["http/1.1", "h2"]),
+               Some("h2".to_owned()));
+    assert_eq!(alpn_roundtrip(&["http/1.1"], &["h2", "http/1.1"]),
+               Some("http/1.1".to_owned()));
+}
+
+#[test]
+fn alpn_mismatch() {
+    // With no protocol in common the handshake succeeds, but no protocol is selected
+    assert_eq!(alpn_roundtrip(&["h2"], &["http/1.1"]), None);
+}
+
+fn sni_roundtrip(servername: &'static str) -> Option<String> {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .add_keypair_file("tests/sni.crt", "tests/sni.key")
+        .bind().unwrap();
+
+    loopback(&tls_srv, move |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        // Name verification fails unless the server picked the right certificate
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, servername).unwrap();
+        tls_stream.handshake().unwrap();
+        assert!(tls_stream.peer_cert_contains_name(servername));
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        tls_conn.servername()
+    }).0
+}
+
+#[test]
+fn sni() {
+    assert_eq!(sni_roundtrip("localhost"), Some("localhost".to_owned()));
+    assert_eq!(sni_roundtrip("example.org"), Some("example.org".to_owned()));
+}
+
+#[test]
+fn sni_keypair_mem() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .add_keypair(include_bytes!("sni.crt"), include_bytes!("sni.key"))
+        .bind();
+    assert!(tls_srv.is_ok());
+}
+
+/// Client handshake against the test server, returning the client result
+fn client_handshake<F>(configure: F) -> telos::TlsResult<()>
+    where F: FnOnce(TcpStream) -> telos::TlsResult<telos::TlsStream<TcpStream>> + Send + 'static
+{
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = try!(configure(tcp_stream));
+        tls_stream.handshake()
+    }).1
+}
+
+#[test]
+fn error_kind_name_mismatch() {
+    let err = client_handshake(|tcp| {
+        telos::new_client().ca_file("tests/ca.crt").connect(tcp, "example.com")
+    }).unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::NameMismatch);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn error_kind_verify() {
+    // The test CA is not trusted
+    let err = client_handshake(|tcp| {
+        telos::new_client().ca_file("tests/cert.pem").connect(tcp, "localhost")
+    }).unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Verify);
+}
+
+#[test]
+fn error_kind_config() {
+    let err = telos::new_client().ciphers("unknown_cipher").connect_stream(io::empty(), "");
+    assert_eq!(err.err().unwrap().kind(), telos::TlsErrorKind::Config);
+}
+
+#[test]
+fn error_kind_closed() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        tls_stream.shutdown().unwrap();
+        let err = tls_stream.write(b"hello").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }, |mut tls_conn| {
+        let mut buf = [0u8; 128];
+        // The close notify
+        assert_eq!(tls_conn.read(&mut buf).unwrap(), 0);
+    });
+}
+
+#[test]
+fn key_file_error_message() {
+    let err = telos::new_server()
+        .key_file("tests/missing.key")
+        .cert_file("tests/server.crt")
+        .bind().err().unwrap();
+    let msg = err.to_string();
+    assert!(msg.contains("key file"));
+    assert!(msg.contains("tests/missing.key"));
+    assert!(msg.contains("No such file"));
+}
+
+fn ocsp_roundtrip(staple: bool, require: bool) -> telos::TlsResult<Option<telos::OcspStatus>> {
+    let mut builder = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt");
+    if staple {
+        builder = builder.ocsp_staple_file("tests/server.ocsp");
+    }
+    handshake_with(builder.bind().unwrap(), move |tcp_stream| {
+        let mut builder = telos::new_client().ca_file("tests/ca.crt");
+        if require {
+            builder = builder.ocsp_require_stapling();
+        }
+        let mut tls_stream = builder.connect(tcp_stream, "localhost").unwrap();
+        try!(tls_stream.handshake());
+        Ok(tls_stream.ocsp_status())
+    }).1
+}
+
+#[test]
+fn ocsp_stapled() {
+    let status = ocsp_roundtrip(true, true).unwrap().unwrap();
+    assert_eq!(status.response_status, telos::OcspResponseStatus::Successful);
+    assert_eq!(status.cert_status, Some(telos::OcspCertStatus::Good));
+    assert_eq!(status.crl_reason, None);
+    assert_eq!(status.revocation_time, None);
+    assert!(status.this_update.unwrap() < status.next_update.unwrap());
+}
+
+#[test]
+fn ocsp_not_stapled() {
+    assert_eq!(ocsp_roundtrip(false, false).unwrap(), None);
+}
+
+#[test]
+fn ocsp_require_stapling() {
+    assert!(ocsp_roundtrip(false, true).is_err());
+}
+
+#[test]
+fn ocsp_staple_mem() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ocsp_staple(include_bytes!("server.ocsp"))
+        .bind();
+    assert!(tls_srv.is_ok());
+}
+
+#[test]
+fn peer_cert_chain_pem() {
+    let server_crt = include_bytes!("server.crt");
+    let ca_crt = include_bytes!("ca.crt");
+    let mut chain = server_crt.to_vec();
+    chain.extend_from_slice(ca_crt);
+
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert(&chain)
+        .bind().unwrap();
+
+    let (_, pem) = loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        assert_eq!(tls_stream.peer_cert_chain_pem(), None);
+        tls_stream.handshake().unwrap();
+        tls_stream.peer_cert_chain_pem().unwrap()
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        // The client did not present a certificate
+        assert_eq!(tls_conn.peer_cert_chain_pem(), None);
+    });
+    let certs: Vec<&[u8]> = telos::pem_certificates(&pem).collect();
+    assert_eq!(certs.len(), 2);
+    assert_eq!(certs[0], &server_crt[..]);
+    assert_eq!(certs[1], &ca_crt[..]);
+}
+
+#[test]
+fn pem_certificates_files() {
+    let ca_crt = include_bytes!("ca.crt");
+    let certs: Vec<&[u8]> = telos::pem_certificates(ca_crt).collect();
+    assert_eq!(certs, vec![&ca_crt[..]]);
+}
+
+/// The certificate hash of tests/server.crt, as seen by a client
+fn server_cert_hash() -> String {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    let (srv_res, hash) = handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        tls_stream.certificate_hash()
+    });
+    srv_res.unwrap();
+    hash
+}
+
+#[test]
+fn pin_hashes() {
+    let hash = server_cert_hash();
+    assert!(hash.starts_with("SHA256:"));
+    client_handshake(move |tcp| {
+        telos::new_client()
+            .ca_file("tests/ca.crt")
+            .pin_hashes(&["SHA256:0000", &hash])
+            .connect(tcp, "localhost")
+    }).unwrap();
+}
+
+#[test]
+fn pin_hashes_noverifycert() {
+    let hash = server_cert_hash();
+    client_handshake(move |tcp| {
+        telos::new_client()
+            .insecure_noverifycert()
+            .pin_hashes(&[&hash])
+            .connect(tcp, "localhost")
+    }).unwrap();
+}
+
+#[test]
+fn pin_hashes_mismatch() {
+    let err = client_handshake(|tcp| {
+        telos::new_client()
+            .ca_file("tests/ca.crt")
+            .pin_hashes(&["SHA256:0000"])
+            .connect(tcp, "localhost")
+    }).unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::PinMismatch);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn pin_hashes_mismatch_write() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .pin_hashes(&["SHA256:0000"])
+                .connect(tcp_stream, "localhost").unwrap();
+        // No data goes out to a server that is not pinned
+        let err = tls_stream.write(b"secret").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(tls_stream.write(b"secret").is_err());
+    }, |mut tls_conn| {
+        let mut buf = [0u8; 128];
+        if let Ok(len) = tls_conn.read(&mut buf) {
+            assert_eq!(len, 0);
+        }
+    });
+}
+
+#[test]
+fn pin_hashes_invalid() {
+    let err = client_handshake(|tcp| {
+        telos::new_client().pin_hashes(&["0000"]).connect(tcp, "localhost")
+    });
+    assert_eq!(err.unwrap_err().kind(), telos::TlsErrorKind::Config);
+}
+
+#[cfg(unix)]
+fn session_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("telos-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_owned()
+}
+
+#[cfg(unix)]
+#[test]
+fn session_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = session_path("session_file");
+    let cli_path = path.clone();
+    client_handshake(move |tcp| {
+        let tls_stream = try!(telos::new_client()
+            .ca_file("tests/ca.crt")
+            .session_file(&cli_path)
+            .connect(tcp, "localhost"));
+        assert!(!tls_stream.session_resumed());
+        Ok(tls_stream)
+    }).unwrap();
+
+    let meta = std::fs::metadata(&path).unwrap();
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn session_file_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = session_path("session_file_permissions");
+    std::fs::File::create(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp = TcpStream::connect(tcp.local_addr().unwrap()).unwrap();
+    let res = telos::new_client()
+        .session_file(&path)
+        .connect(tcp, "localhost");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(res.err().unwrap().kind(), telos::TlsErrorKind::Config);
+}
+
+/// One loopback connection to `tls_srv` using the client session file at `session`,
+/// returns whether the client and server resumed the session
+#[cfg(unix)]
+fn session_connection(tls_srv: &telos::TlsServer, session: &str) -> (bool, bool) {
+    let session = session.to_owned();
+    let (srv_resumed, cli_resumed) = loopback(tls_srv, move |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                // libtls only resumes TLSv1.2 sessions
+                .protocols(telos::Protocols::TLSV1_2)
+                .session_file(&session)
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        let mut buf = [0u8; 1];
+        tls_stream.read(&mut buf).unwrap();
+        tls_stream.session_resumed()
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        tls_conn.write(b"x").unwrap();
+        tls_conn.session_resumed()
+    });
+    (cli_resumed, srv_resumed)
+}
+
+#[cfg(unix)]
+#[test]
+fn session_resumption() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .session_lifetime(Duration::from_secs(300))
+        .bind().unwrap();
+
+    let path = session_path("session_resumption");
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+    assert_eq!(session_connection(&tls_srv, &path), (true, true));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn session_disabled() {
+    // Without a session lifetime the server does not resume sessions
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap();
+
+    let path = session_path("session_disabled");
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn ticket_key_rotation() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .session_id(b"telos-test")
+        .session_lifetime(Duration::from_secs(300))
+        .ticket_key(1, &[1u8; 48])
+        .bind().unwrap();
+
+    let path = session_path("ticket_key_rotation");
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+
+    // Tickets issued with the previous key are still accepted
+    tls_srv.add_ticket_key(2, &[2u8; 48]).unwrap();
+    assert_eq!(session_connection(&tls_srv, &path), (true, true));
+
+    // Adding the current key again is fine, reusing a revision is not
+    tls_srv.add_ticket_key(2, &[2u8; 48]).unwrap();
+    let err = tls_srv.add_ticket_key(1, &[3u8; 48]).unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn ticket_key_reconfigure() {
+    let config = |keys: &[(u32, [u8; 48])]| {
+        keys.iter()
+            .fold(telos::new_server()
+                      .key_file("tests/server.key")
+                      .cert_file("tests/server.crt")
+                      .session_id(b"telos-test")
+                      .session_lifetime(Duration::from_secs(300)),
+                  |builder, &(rev, ref key)| builder.ticket_key(rev, key))
+            .build()
+            .unwrap()
+    };
+    // Shared between threads
+    let tls_srv = Arc::new(config(&[(1, [1; 48])]).bind().unwrap());
+
+    let path = session_path("ticket_key_reconfigure");
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+
+    tls_srv.reconfigure(&config(&[(1, [1; 48]), (2, [2; 48])])).unwrap();
+    assert_eq!(session_connection(&tls_srv, &path), (true, true));
+
+    // Without the previous key the ticket is no longer accepted
+    tls_srv.reconfigure(&config(&[(3, [3; 48])])).unwrap();
+    assert_eq!(session_connection(&tls_srv, &path), (false, false));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reconfigure_pending_handshake() {
+    let config = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .build()
+        .unwrap();
+    let tls_srv = config.bind().unwrap();
+    let (srv_res, cli_res) = loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        connect_localhost(telos::new_client().ca_file("tests/ca.crt"), tcp_stream)
+    }, |mut tls_conn| {
+        // The connection keeps the server context it was accepted from
+        tls_srv.reconfigure(&config).unwrap();
+        tls_srv.reconfigure(&config).unwrap();
+        tls_conn.handshake()
+    });
+    srv_res.unwrap();
+    cli_res.unwrap();
+}
+
+#[test]
+fn ticket_key_in_use() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ticket_key(1, &[1u8; 48])
+        .bind().unwrap();
+
+    // Keys are rotated while a connection is open, the connection carries on
+    let (srv_res, cli_res) = loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .connect(tcp_stream, "localhost").unwrap();
+        let mut buf = [0u8; 5];
+        tls_stream.read_exact(&mut buf).map(|_| buf)
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        tls_srv.add_ticket_key(2, &[2u8; 48]).unwrap();
+        tls_conn.write_all(b"hello")
+    });
+    srv_res.unwrap();
+    assert_eq!(&cli_res.unwrap(), b"hello");
+
+    // A failed rotation keeps the current keys
+    let err = tls_srv.add_ticket_key(3, &[3u8; 16]).unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+    tls_srv.add_ticket_key(2, &[2u8; 48]).unwrap();
+}
+
+#[test]
+fn ticket_key_invalid() {
+    let err = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ticket_key(1, &[1u8; 16])
+        .bind().err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+}
+
+#[test]
+fn ticket_key_rev() {
+    let rev = telos::TlsServer::ticket_key_rev(Duration::from_secs(3600));
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+    assert!(rev as u64 >= now.as_secs() / 3600 - 1);
+    assert!(rev as u64 <= now.as_secs() / 3600);
+    assert!(telos::TlsServer::ticket_key_rev(Duration::from_secs(0)) > rev);
+}
+
+#[test]
+fn protocol_version() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .protocols(telos::Protocols::TLSV1_2)
+        .bind().unwrap();
+
+    let (srv_version, cli_version) = loopback(&tls_srv, |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .protocols(telos::Protocols::SECURE)
+                .connect(tcp_stream, "localhost").unwrap();
+        assert_eq!(tls_stream.protocol_version(), None);
+        tls_stream.handshake().unwrap();
+        tls_stream.protocol_version()
+    }, |mut tls_conn| {
+        tls_conn.handshake().unwrap();
+        tls_conn.protocol_version()
+    });
+    assert_eq!(srv_version, Some(telos::Protocol::Tlsv1_2));
+    assert_eq!(cli_version, Some(telos::Protocol::Tlsv1_2));
+}
+
+#[test]
+fn protocol_mismatch() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .protocols(telos::Protocols::TLSV1_3)
+        .bind().unwrap();
+
+    let (srv_res, cli_res) = handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .protocols(telos::Protocols::TLSV1_2)
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake()
+    });
+    assert!(srv_res.is_err());
+    assert!(cli_res.is_err());
+}
+
+#[test]
+fn server_settings() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .protocols(telos::Protocols::TLSV1_2)
+        .ciphers("ECDHE-RSA-AES256-GCM-SHA384")
+        .prefer_ciphers_server()
+        .verify_depth(2)
+        .bind().unwrap();
+
+    let (srv_res, cipher) = handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = telos::new_client()
+                .ca_file("tests/ca.crt")
+                .prefer_ciphers_client()
+                .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        tls_stream.cipher()
+    });
+    srv_res.unwrap();
+    assert_eq!(cipher, "ECDHE-RSA-AES256-GCM-SHA384");
+}
+
+#[test]
+fn server_ciphers_invalid() {
+    let err = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ciphers("no-such-cipher")
+        .bind().err().unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+}
+
+/// Handshake between a server and client configured by `srv_cfg` and `cli_cfg`,
+/// returns the key exchange seen by the client
+fn kex_handshake<S, C>(srv_cfg: S, cli_cfg: C) -> telos::TlsResult<Option<telos::KeyExchange>>
+    where S: FnOnce(telos::ServerBuilder) -> telos::ServerBuilder,
+          C: FnOnce(telos::ClientBuilder) -> telos::ClientBuilder + Send + 'static
+{
+    let tls_srv = srv_cfg(telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")).bind().unwrap();
+
+    handshake_with(tls_srv, |tcp_stream| {
+        let mut tls_stream = cli_cfg(telos::new_client().ca_file("tests/ca.crt"))
+                .connect(tcp_stream, "localhost").unwrap();
+        assert_eq!(tls_stream.key_exchange(), None);
+        try!(tls_stream.handshake());
+        Ok(tls_stream.key_exchange())
+    }).1
+}
+
+#[test]
+fn ecdhe_curves() {
+    let kex = kex_handshake(|srv| srv.ecdhe_curves(&[telos::Curve::P384, telos::Curve::P256]),
+                            |cli| cli.ecdhe_curves(&[telos::Curve::P256]));
+    assert_eq!(kex.unwrap(), Some(telos::KeyExchange::Ecdhe));
+}
+
+#[test]
+fn ecdhe_curves_mismatch() {
+    let res = kex_handshake(|srv| srv.ecdhe_curves(&[telos::Curve::P384]),
+                            |cli| cli.ecdhe_curves(&[telos::Curve::X25519]));
+    assert!(res.is_err());
+}
+
+#[test]
+fn dhe_params() {
+    let kex = kex_handshake(|srv| {
+                                srv.protocols(telos::Protocols::TLSV1_2)
+                                    .ciphers("DHE-RSA-AES256-GCM-SHA384")
+                                    .dhe_params(telos::DheParams::Auto)
+                            },
+                            |cli| cli);
+    assert_eq!(kex.unwrap(), Some(telos::KeyExchange::Dhe));
+}
+
+#[test]
+fn dhe_params_none() {
+    // Without DHE parameters the server has no usable cipher
+    let res = kex_handshake(|srv| {
+                                srv.protocols(telos::Protocols::TLSV1_2)
+                                    .ciphers("DHE-RSA-AES256-GCM-SHA384")
+                                    .dhe_params(telos::DheParams::None)
+                            },
+                            |cli| cli);
+    assert!(res.is_err());
+}
+
+/// Client handshake with the client built by `cli_cfg`
+fn connect_localhost(cli_cfg: telos::ClientBuilder, tcp: TcpStream) -> telos::TlsResult<()> {
+    cli_cfg.connect(tcp, "localhost").and_then(|mut tls_stream| tls_stream.handshake())
+}
+
+#[test]
+fn crl_revoked_server() {
+    let srv = telos::new_server()
+        .key_file("tests/revoked.key")
+        .cert_file("tests/revoked.crt");
+    let (_, cli_res) = handshake_with(srv.bind().unwrap(), |tcp| {
+        connect_localhost(telos::new_client().ca_file("tests/ca.crt").crl_file("tests/ca.crl"),
+                          tcp)
+    });
+    let err = cli_res.unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Revoked);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn crl_not_revoked() {
+    let srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt");
+    let (srv_res, cli_res) = handshake_with(srv.bind().unwrap(), |tcp| {
+        connect_localhost(telos::new_client().ca_file("tests/ca.crt").crl_file("tests/ca.crl"),
+                          tcp)
+    });
+    srv_res.unwrap();
+    cli_res.unwrap();
+}
+
+#[test]
+fn crl_not_configured() {
+    // Without the CRL the revoked certificate is accepted
+    let srv = telos::new_server()
+        .key_file("tests/revoked.key")
+        .cert_file("tests/revoked.crt");
+    let (_, cli_res) = handshake_with(srv.bind().unwrap(), |tcp| {
+        connect_localhost(telos::new_client().ca_file("tests/ca.crt"), tcp)
+    });
+    cli_res.unwrap();
+}
+
+#[test]
+fn crl_revoked_client() {
+    let srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ca_file("tests/ca.crt")
+        .crl(include_bytes!("ca.crl"))
+        .verify_client();
+    // With TLSv1.3 the client may complete its side of the handshake before
+    // the server rejects the certificate
+    let (srv_res, _) = handshake_with(srv.bind().unwrap(), |tcp| {
+        let cli = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .cert_file("tests/revoked.crt")
+            .key_file("tests/revoked.key");
+        connect_localhost(cli, tcp)
+    });
+    assert_eq!(srv_res.unwrap_err().kind(), telos::TlsErrorKind::Revoked);
+}
+
+#[test]
+fn crl_revoked_client_optional() {
+    // A revoked certificate fails even when client certificates are optional
+    let srv = telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .ca_file("tests/ca.crt")
+        .crl_file("tests/ca.crl")
+        .verify_client_optional();
+    let (srv_res, _) = handshake_with(srv.bind().unwrap(), |tcp| {
+        let cli = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .cert_file("tests/revoked.crt")
+            .key_file("tests/revoked.key");
+        connect_localhost(cli, tcp)
+    });
+    let err = srv_res.unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Revoked);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn crl_file_missing() {
+    let err = telos::new_client()
+        .crl_file("tests/no-such.crl")
+        .connect_stream(io::Cursor::new(Vec::new()), "localhost")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Config);
+}
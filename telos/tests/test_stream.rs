@@ -0,0 +1,145 @@
+extern crate telos;
+use std::cell::Cell;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use telos::TlsBuilder;
+
+/// A stream that fails all I/O with the given error kind
+struct FailingStream(io::ErrorKind);
+
+impl Read for FailingStream {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.0, "read failed"))
+    }
+}
+
+impl Write for FailingStream {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.0, "write failed"))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream that accepts all writes, and panics on read
+struct PanicStream;
+
+impl Read for PanicStream {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        panic!("PanicStream read");
+    }
+}
+
+impl Write for PanicStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream that is not Send, counts the bytes written and blocks on read
+struct LocalStream(Rc<Cell<usize>>);
+
+impl Read for LocalStream {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::WouldBlock, "no data"))
+    }
+}
+
+impl Write for LocalStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.set(self.0.get() + buf.len());
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn stream_loopback() {
+    let tls_srv = telos::new_server()
+        .key_file("tests/private_key.key")
+        .cert_file("tests/certificate.crt")
+        .bind().unwrap();
+
+    let srv = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = srv.local_addr().unwrap();
+
+    let cli = thread::spawn(move ||{
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+                .insecure_noverifyname()
+                .insecure_noverifycert()
+                .connect_stream(tcp_stream, "").unwrap();
+        let mut buf = [0u8; 128];
+        let len = tls_stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        tls_stream.write(b"world").unwrap();
+        assert_eq!(tls_stream.inner().get_ref().peer_addr().unwrap(), addr);
+    });
+
+    let tcp_conn = srv.incoming().next().unwrap().unwrap();
+    let mut tls_conn = tls_srv.accept_stream(tcp_conn).unwrap();
+    tls_conn.write(b"hello").unwrap();
+    let mut buf = [0u8; 128];
+    let len = tls_conn.read(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"world");
+
+    cli.join().unwrap();
+}
+
+#[test]
+fn stream_io_error() {
+    let mut cli = telos::new_client()
+        .insecure_noverifycert()
+        .connect_stream(FailingStream(io::ErrorKind::ConnectionReset), "localhost")
+        .unwrap();
+    let err = cli.handshake().unwrap_err();
+    assert_eq!(err.kind(), telos::TlsErrorKind::Io(None));
+    assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn stream_would_block() {
+    let mut cli = telos::new_client()
+        .insecure_noverifycert()
+        .connect_stream(FailingStream(io::ErrorKind::WouldBlock), "localhost")
+        .unwrap();
+    let err = cli.handshake().unwrap_err();
+    assert!(err.wants_more());
+    assert_eq!(err.kind(), telos::TlsErrorKind::WantPollOut);
+    // The client hello is the first thing sent
+    assert_eq!(err.interest(), Some(telos::Interest::Write));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+fn stream_not_send() {
+    let written = Rc::new(Cell::new(0));
+    let mut cli = telos::new_client()
+        .insecure_noverifycert()
+        .connect_stream(LocalStream(written.clone()), "localhost")
+        .unwrap();
+    let err = cli.handshake().unwrap_err();
+    assert_eq!(err.interest(), Some(telos::Interest::Read));
+    // The client hello went through the stream
+    assert!(written.get() > 0);
+}
+
+#[test]
+#[should_panic(expected = "PanicStream read")]
+fn stream_panic() {
+    let mut cli = telos::new_client()
+        .insecure_noverifycert()
+        .connect_stream(PanicStream, "localhost")
+        .unwrap();
+    let _ = cli.handshake();
+}
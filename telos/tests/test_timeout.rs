@@ -0,0 +1,160 @@
+#![cfg(unix)]
+extern crate telos;
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use telos::{TlsBuilder, TlsErrorKind, TlsStream};
+use common::loopback;
+
+/// The server for the timeout tests
+fn local_server() -> telos::TlsServer {
+    telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind()
+        .unwrap()
+}
+
+/// Run `client` on a handshaked client connection. The server connection is
+/// handed to `server` after its handshake and kept open until `client` returns
+fn connected<S, C>(server: S, client: C)
+    where S: FnOnce(&mut TlsStream<TcpStream>),
+          C: FnOnce(&mut TlsStream<TcpStream>) + Send + 'static
+{
+    let (done, wait) = mpsc::channel();
+    loopback(&local_server(), move |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let mut tls_stream = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .connect(tcp_stream, "localhost").unwrap();
+        tls_stream.handshake().unwrap();
+        client(&mut tls_stream);
+        done.send(()).unwrap();
+    }, |mut conn| {
+        conn.handshake().unwrap();
+        server(&mut conn);
+        let _ = wait.recv();
+    });
+}
+
+#[test]
+fn read_timeout() {
+    connected(|_| {}, |tls_stream| {
+        tls_stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        assert_eq!(tls_stream.read_timeout(), Some(Duration::from_millis(100)));
+
+        let start = Instant::now();
+        let mut buf = [0u8; 1];
+        let err = tls_stream.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    });
+}
+
+#[test]
+fn read_timeout_data() {
+    connected(|conn| {
+        thread::sleep(Duration::from_millis(50));
+        conn.write_all(b"ping").unwrap();
+    }, |tls_stream| {
+        tls_stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut buf = [0u8; 4];
+        tls_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    });
+}
+
+#[test]
+fn read_timeout_reset() {
+    connected(|conn| {
+        thread::sleep(Duration::from_millis(300));
+        conn.write_all(b"ping").unwrap();
+    }, |tls_stream| {
+        tls_stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let mut buf = [0u8; 4];
+        assert!(tls_stream.read(&mut buf).is_err());
+
+        // The socket is blocking again
+        tls_stream.set_read_timeout(None).unwrap();
+        tls_stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    });
+}
+
+#[test]
+fn write_timeout() {
+    // The server never reads, eventually the socket buffers fill up
+    connected(|_| {}, |tls_stream| {
+        tls_stream.set_write_timeout(Some(Duration::from_millis(100))).unwrap();
+        let buf = [0u8; 16 * 1024];
+        let err = loop {
+            if let Err(err) = tls_stream.write(&buf) {
+                break err;
+            }
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    });
+}
+
+#[test]
+fn handshake_timeout() {
+    // A TCP server that never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut tls_stream = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .connect(tcp_stream, "localhost").unwrap();
+
+    let err = tls_stream.handshake_timeout(Duration::from_millis(100)).unwrap_err();
+    assert_eq!(err.kind(), TlsErrorKind::Timeout);
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn connect_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let start = Instant::now();
+    let err = telos::new_client()
+        .ca_file("tests/ca.crt")
+        .connect_timeout(Duration::from_millis(100))
+        .connect(tcp_stream, "localhost")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), TlsErrorKind::Timeout);
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn connect_timeout_handshake() {
+    loopback(&local_server(), |addr| {
+        let tcp_stream = TcpStream::connect(addr).unwrap();
+        let tls_stream = telos::new_client()
+            .ca_file("tests/ca.crt")
+            .connect_timeout(Duration::from_secs(10))
+            .connect(tcp_stream, "localhost").unwrap();
+        // The handshake is already done
+        assert!(!tls_stream.version().is_empty());
+    }, |mut conn| conn.handshake().unwrap());
+}
+
+#[test]
+fn timeout_invalid() {
+    connected(|_| {}, |tls_stream| {
+        let err = tls_stream.set_read_timeout(Some(Duration::new(0, 0))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(tls_stream.read_timeout(), None);
+    });
+
+    // Callback streams have no socket to poll
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut tls_stream = telos::new_client()
+        .connect_stream(tcp_stream, "localhost").unwrap();
+    let err = tls_stream.set_write_timeout(Some(Duration::from_secs(1))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "tokio")]
+extern crate telos;
+extern crate tokio;
+
+use std::thread;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{Builder, Runtime};
+use telos::TlsBuilder;
+use telos::tokio_support::{TlsAcceptor, TlsConnector};
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().enable_io().build().unwrap()
+}
+
+#[test]
+fn tokio_loopback() {
+    let rt = runtime();
+    let srv = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = srv.local_addr().unwrap();
+
+    let cli = thread::spawn(move || {
+        let rt = runtime();
+        let tcp = rt.block_on(TcpStream::connect(addr)).unwrap();
+        let connector = TlsConnector::new(telos::new_client().ca_file("tests/ca.crt").build().unwrap());
+        let mut stream = rt.block_on(connector.connect(tcp, "localhost")).unwrap();
+        assert!(stream.get_ref().peer_cert_contains_name("localhost"));
+
+        let mut buf = [0u8; 5];
+        rt.block_on(stream.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"hello");
+        rt.block_on(stream.write_all(b"world")).unwrap();
+        rt.block_on(stream.shutdown()).unwrap();
+    });
+
+    let acceptor = TlsAcceptor::new(telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap());
+    let (tcp, _) = rt.block_on(srv.accept()).unwrap();
+    let mut stream = rt.block_on(acceptor.accept(tcp)).unwrap();
+    rt.block_on(stream.write_all(b"hello")).unwrap();
+    let mut buf = Vec::new();
+    // read_to_end stops at the close notify
+    rt.block_on(stream.read_to_end(&mut buf)).unwrap();
+    assert_eq!(&buf[..], b"world");
+
+    cli.join().unwrap();
+}
+
+#[test]
+fn tokio_handshake_error() {
+    let rt = runtime();
+    let srv = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = srv.local_addr().unwrap();
+
+    let cli = thread::spawn(move || {
+        let rt = runtime();
+        let tcp = rt.block_on(TcpStream::connect(addr)).unwrap();
+        // The server certificate is not valid for this name
+        let connector = TlsConnector::new(telos::new_client().ca_file("tests/ca.crt").build().unwrap());
+        assert!(rt.block_on(connector.connect(tcp, "example.com")).is_err());
+    });
+
+    let acceptor = TlsAcceptor::new(telos::new_server()
+        .key_file("tests/server.key")
+        .cert_file("tests/server.crt")
+        .bind().unwrap());
+    let (tcp, _) = rt.block_on(srv.accept()).unwrap();
+    let _ = rt.block_on(acceptor.accept(tcp));
+
+    cli.join().unwrap();
+}
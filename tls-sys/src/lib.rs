@@ -7,6 +7,44 @@ pub type Config = *mut c_void;
 pub const WANT_POLLIN: i64 = -2;
 pub const WANT_POLLOUT: i64 = -3;
 
+pub const PROTOCOL_TLSV1_0: uint32_t = 1 << 1;
+pub const PROTOCOL_TLSV1_1: uint32_t = 1 << 2;
+pub const PROTOCOL_TLSV1_2: uint32_t = 1 << 3;
+pub const PROTOCOL_TLSV1_3: uint32_t = 1 << 4;
+
+pub const OCSP_RESPONSE_SUCCESSFUL: c_int = 0;
+pub const OCSP_RESPONSE_MALFORMED: c_int = 1;
+pub const OCSP_RESPONSE_INTERNALERROR: c_int = 2;
+pub const OCSP_RESPONSE_TRYLATER: c_int = 3;
+pub const OCSP_RESPONSE_SIGREQUIRED: c_int = 4;
+pub const OCSP_RESPONSE_UNAUTHORIZED: c_int = 5;
+
+pub const OCSP_CERT_GOOD: c_int = 0;
+pub const OCSP_CERT_REVOKED: c_int = 1;
+pub const OCSP_CERT_UNKNOWN: c_int = 2;
+
+pub const CRL_REASON_UNSPECIFIED: c_int = 0;
+pub const CRL_REASON_KEY_COMPROMISE: c_int = 1;
+pub const CRL_REASON_CA_COMPROMISE: c_int = 2;
+pub const CRL_REASON_AFFILIATION_CHANGED: c_int = 3;
+pub const CRL_REASON_SUPERSEDED: c_int = 4;
+pub const CRL_REASON_CESSATION_OF_OPERATION: c_int = 5;
+pub const CRL_REASON_CERTIFICATE_HOLD: c_int = 6;
+pub const CRL_REASON_REMOVE_FROM_CRL: c_int = 8;
+pub const CRL_REASON_PRIVILEGE_WITHDRAWN: c_int = 9;
+pub const CRL_REASON_AA_COMPROMISE: c_int = 10;
+
+pub const MAX_SESSION_ID_LENGTH: size_t = 32;
+pub const TICKET_KEY_SIZE: size_t = 48;
+
+pub type ReadCb = extern "C" fn(ctx: Tls, buf: *mut c_void, buflen: size_t, cb_arg: *mut c_void)
+                                -> ssize_t;
+pub type WriteCb = extern "C" fn(ctx: Tls,
+                                 buf: *const c_void,
+                                 buflen: size_t,
+                                 cb_arg: *mut c_void)
+                                 -> ssize_t;
+
 extern "C" {
     pub fn tls_init() -> c_int;
     pub fn tls_free(ctx: Tls);
@@ -15,20 +53,69 @@ extern "C" {
 
     pub fn tls_config_new() -> Config;
     pub fn tls_config_free(cfg: Config);
+    pub fn tls_config_error(cfg: Config) -> *const c_char;
     pub fn tls_config_set_ca_file(cfg: Config, ca_file: *const c_char) -> c_int;
     pub fn tls_config_set_ca_path(cfg: Config, ca_file: *const c_char) -> c_int;
     pub fn tls_config_set_ca_mem(cfg: Config, ca: *const uint8_t, len: size_t) -> c_int;
+    pub fn tls_config_set_crl_file(cfg: Config, crl_file: *const c_char) -> c_int;
+    pub fn tls_config_set_crl_mem(cfg: Config, crl: *const uint8_t, len: size_t) -> c_int;
     pub fn tls_config_set_verify_depth(cfg: Config, depth: c_int);
     pub fn tls_config_set_key_file(cfg: Config, key_file: *const c_char) -> c_int;
     pub fn tls_config_set_cert_file(cfg: Config, key_file: *const c_char) -> c_int;
+    pub fn tls_config_set_key_mem(cfg: Config, key: *const uint8_t, len: size_t) -> c_int;
+    pub fn tls_config_set_cert_mem(cfg: Config, cert: *const uint8_t, len: size_t) -> c_int;
+    pub fn tls_config_set_keypair_mem(cfg: Config,
+                                      cert: *const uint8_t,
+                                      cert_len: size_t,
+                                      key: *const uint8_t,
+                                      key_len: size_t)
+                                      -> c_int;
+    pub fn tls_config_set_session_fd(cfg: Config, fd: c_int) -> c_int;
+    pub fn tls_config_set_session_id(cfg: Config,
+                                     session_id: *const uint8_t,
+                                     len: size_t)
+                                     -> c_int;
+    pub fn tls_config_set_session_lifetime(cfg: Config, lifetime: c_int) -> c_int;
+    pub fn tls_config_add_ticket_key(cfg: Config,
+                                     keyrev: uint32_t,
+                                     key: *mut uint8_t,
+                                     keylen: size_t)
+                                     -> c_int;
+    pub fn tls_config_clear_keys(cfg: Config);
+    pub fn tls_config_add_keypair_file(cfg: Config,
+                                       cert_file: *const c_char,
+                                       key_file: *const c_char)
+                                       -> c_int;
+    pub fn tls_config_add_keypair_mem(cfg: Config,
+                                      cert: *const uint8_t,
+                                      cert_len: size_t,
+                                      key: *const uint8_t,
+                                      key_len: size_t)
+                                      -> c_int;
     pub fn tls_config_insecure_noverifyname(cfg: Config);
     pub fn tls_config_insecure_noverifycert(cfg: Config);
+    pub fn tls_config_prefer_ciphers_server(cfg: Config);
+    pub fn tls_config_prefer_ciphers_client(cfg: Config);
+    pub fn tls_config_verify_client(cfg: Config);
+    pub fn tls_config_verify_client_optional(cfg: Config);
     pub fn tls_config_set_protocols(cfg: Config, protocols: uint32_t);
     pub fn tls_config_parse_protocols(protocols: *mut uint32_t, protocols: *const c_char) -> c_int;
     pub fn tls_config_set_ciphers(cfg: Config, ciphers: *const c_char) -> c_int;
+    pub fn tls_config_set_ecdhecurves(cfg: Config, curves: *const c_char) -> c_int;
+    pub fn tls_config_set_dheparams(cfg: Config, params: *const c_char) -> c_int;
+    pub fn tls_config_set_alpn(cfg: Config, alpn: *const c_char) -> c_int;
+    pub fn tls_config_set_ocsp_staple_file(cfg: Config, staple_file: *const c_char) -> c_int;
+    pub fn tls_config_set_ocsp_staple_mem(cfg: Config,
+                                          staple: *const uint8_t,
+                                          len: size_t)
+                                          -> c_int;
+    pub fn tls_config_ocsp_require_stapling(cfg: Config);
 
     pub fn tls_conn_version(ctx: Tls) -> *const c_char;
     pub fn tls_conn_cipher(ctx: Tls) -> *const c_char;
+    pub fn tls_conn_alpn_selected(ctx: Tls) -> *const c_char;
+    pub fn tls_conn_session_resumed(ctx: Tls) -> c_int;
+    pub fn tls_conn_servername(ctx: Tls) -> *const c_char;
 
     pub fn tls_peer_cert_notbefore(ctx: Tls) -> time_t;
     pub fn tls_peer_cert_notafter(ctx: Tls) -> time_t;
@@ -37,6 +124,16 @@ extern "C" {
     pub fn tls_peer_cert_hash(ctx: Tls) -> *const c_char;
     pub fn tls_peer_cert_contains_name(ctx: Tls, name: *const c_char) -> c_int;
     pub fn tls_peer_cert_provided(ctx: Tls) -> c_int;
+    pub fn tls_peer_cert_chain_pem(ctx: Tls, len: *mut size_t) -> *const uint8_t;
+
+    pub fn tls_peer_ocsp_cert_status(ctx: Tls) -> c_int;
+    pub fn tls_peer_ocsp_crl_reason(ctx: Tls) -> c_int;
+    pub fn tls_peer_ocsp_next_update(ctx: Tls) -> time_t;
+    pub fn tls_peer_ocsp_response_status(ctx: Tls) -> c_int;
+    pub fn tls_peer_ocsp_result(ctx: Tls) -> *const c_char;
+    pub fn tls_peer_ocsp_revocation_time(ctx: Tls) -> time_t;
+    pub fn tls_peer_ocsp_this_update(ctx: Tls) -> time_t;
+    pub fn tls_peer_ocsp_url(ctx: Tls) -> *const c_char;
 
     pub fn tls_client() -> Tls;
     pub fn tls_connect(ctx: Tls, hostname: *const c_char, port: *const c_char) -> c_int;
@@ -51,6 +148,12 @@ extern "C" {
                            servername: *const c_char)
                            -> c_int;
     pub fn tls_connect_socket(ctx: Tls, fd: c_int, servername: *const c_char) -> c_int;
+    pub fn tls_connect_cbs(ctx: Tls,
+                           read_cb: ReadCb,
+                           write_cb: WriteCb,
+                           cb_arg: *mut c_void,
+                           servername: *const c_char)
+                           -> c_int;
     pub fn tls_handshake(ctx: Tls) -> c_int;
     pub fn tls_read(ctx: Tls, buf: *mut c_void, buflen: size_t) -> ssize_t;
     pub fn tls_write(ctx: Tls, buf: *const c_void, buflen: size_t) -> ssize_t;
@@ -58,6 +161,12 @@ extern "C" {
 
     pub fn tls_server() -> Tls;
     pub fn tls_accept_socket(ctx: Tls, cctx: *mut Tls, fd: c_int) -> c_int;
+    pub fn tls_accept_cbs(ctx: Tls,
+                          cctx: *mut Tls,
+                          read_cb: ReadCb,
+                          write_cb: WriteCb,
+                          cb_arg: *mut c_void)
+                          -> c_int;
 }
 
 // A minimal test, enough to force a sanity check on the linkage
@@ -26,3 +26,31 @@
 # error "API error TLS_WANT_POLLOUT -3 !=", TLS_WANT_POLLOUT
 #endif
 
+// Protocol bits, TLSv1.3 was added in libressl 3.2
+#ifndef TLS_PROTOCOL_TLSv1_3
+# error "TLS_PROTOCOL_TLSv1_3 is undefined, is this version of libtls too old?"
+#endif
+
+#if TLS_PROTOCOL_TLSv1_0 != (1 << 1) || TLS_PROTOCOL_TLSv1_1 != (1 << 2) || \
+    TLS_PROTOCOL_TLSv1_2 != (1 << 3) || TLS_PROTOCOL_TLSv1_3 != (1 << 4)
+# error "API error TLS_PROTOCOL_* values changed"
+#endif
+
+// OCSP status values, added in libressl 2.5
+#ifndef TLS_OCSP_CERT_GOOD
+# error "TLS_OCSP_CERT_GOOD is undefined, is this version of libtls too old?"
+#endif
+
+#if TLS_OCSP_CERT_GOOD != 0 || TLS_OCSP_CERT_REVOKED != 1 || TLS_OCSP_CERT_UNKNOWN != 2
+# error "API error TLS_OCSP_CERT_* values changed"
+#endif
+
+#if TLS_OCSP_RESPONSE_SUCCESSFUL != 0 || TLS_CRL_REASON_AA_COMPROMISE != 10
+# error "API error TLS_OCSP_RESPONSE_*/TLS_CRL_REASON_* values changed"
+#endif
+
+
+// Session cache sizes, added in libressl 2.6
+#if TLS_MAX_SESSION_ID_LENGTH != 32 || TLS_TICKET_KEY_SIZE != 48
+# error "API error TLS_MAX_SESSION_ID_LENGTH/TLS_TICKET_KEY_SIZE values changed"
+#endif